@@ -2,16 +2,38 @@
 //   to a department in a company. For example, "Add Sally to Engineering" or "Add Amir to Sales."
 // Then let the user retrieve a list of all people in a department or all people in the company by 
 //   department, sorted alphabetically.
+//
+// The employees are kept in a file, employees.txt or the one given with --db <path>, with one
+//   "<department> <name>" per line. It's loaded at startup and saved again after every Add.
 
 use std::collections::HashMap;
+use std::env;
+use std::fs;
 use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
 
 // required trait for .lines()
 use std::io::BufRead;
+// required trait for .write_all()
+use std::io::Write;
+
+const DEFAULT_DB: &str = "employees.txt";
+
+type Employees = HashMap<String, Vec<String>>;
 
 fn main() {
-    let mut employees: HashMap<String, Vec<String>> = HashMap::new();
+    let db = db_path().unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        eprintln!("usage: employees [--db <path>]");
+        process::exit(2);
+    });
+    let mut employees = load(&db).unwrap_or_else(|err| {
+        eprintln!("error: unable to load {}: {}", db.display(), err);
+        process::exit(1);
+    });
     let stdin = io::stdin();
+    println!("Using {}", db.display());
     println!("Type 'Add <name> to <department>' to add an employee");
     println!("Type 'List <department>' to list the employees of a department");
     println!("Type 'All' to list all employees by department");
@@ -20,7 +42,13 @@ fn main() {
         let input = line.expect("error: unable to read user input");
         match Command::from_input(&input) {
             // or_default is just a convenience, does the same as or_insert_with(Vec::default)
-            Some(Command::Add { dept, name }) => employees.entry(dept).or_default().push(name),
+            Some(Command::Add { dept, name }) => {
+                employees.entry(dept).or_default().push(name);
+                // Saving after every change means nothing is lost if the program is killed.
+                if let Err(err) = save(&db, &employees) {
+                    eprintln!("error: unable to save {}: {}", db.display(), err);
+                }
+            }
             Some(Command::List(dept)) => match employees.get(&dept) {
                 Some(names) => {
                     for name in names {
@@ -46,6 +74,76 @@ fn main() {
     println!("Have a nice day!");
 }
 
+// The file given with --db <path>, or DEFAULT_DB.
+fn db_path() -> Result<PathBuf, String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => Ok(PathBuf::from(DEFAULT_DB)),
+        [flag, path] if flag == "--db" => Ok(PathBuf::from(path)),
+        [flag] if flag == "--db" => Err("--db needs a path".to_string()),
+        [arg, ..] => Err(format!("unexpected argument '{}'", arg)),
+    }
+}
+
+// A file that doesn't exist yet is just an empty company.
+fn load(path: &Path) -> io::Result<Employees> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Employees::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut employees = Employees::new();
+    for (number, line) in contents.lines().enumerate() {
+        // Names and departments are single words, so the first space separates them.
+        match line.split_once(' ') {
+            Some((dept, name)) if !dept.is_empty() && !name.is_empty() => {
+                employees.entry(dept.to_string()).or_default().push(name.to_string())
+            }
+            _ if line.trim().is_empty() => {}
+            _ => {
+                let message = format!("line {}: expected '<department> <name>'", number + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        }
+    }
+    Ok(employees)
+}
+
+// Writes to a temporary file next to `path` and renames it over `path`, so that a crash
+//   in the middle of saving leaves either the old file or the new one, never half of one.
+fn save(path: &Path, employees: &Employees) -> io::Result<()> {
+    let mut contents = String::new();
+    // Sorted, so that the file doesn't change order from one save to the next.
+    let mut depts: Vec<&String> = employees.keys().collect();
+    depts.sort();
+    for dept in depts {
+        for name in &employees[dept] {
+            contents.push_str(&format!("{} {}\n", dept, name));
+        }
+    }
+
+    let tmp = temp_path(path);
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        // Make sure the data is on disk before the rename makes it the real file.
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+// A hidden file in the same directory, so the rename stays on one file system, and named
+//   after this process, so two copies of the program saving at once don't share it.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, process::id()))
+}
+
 enum Command {
     // Using named fields instead of Add(String, String) because dept and name
     // are the same type and could get mixed up.
@@ -56,6 +154,7 @@ enum Command {
 }
 
 impl Command {
+    #[allow(clippy::trim_split_whitespace)]
     fn from_input(s: &str) -> Option<Self> {
         let words: Vec<&str> = s.trim().split_whitespace().collect();
         // "Slice destructuring / slice pattern matching" for more info
@@ -70,4 +169,54 @@ impl Command {
             _ => None,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A directory in the temp directory, unique to this test run, that is removed again when
+    //   dropped, even if the test fails.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir = env::temp_dir().join(format!("employees-{}-{}", process::id(), name));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn save_then_load_gives_the_same_employees() {
+        let dir = TempDir::new("roundtrip");
+        let path = dir.0.join("employees.txt");
+        let mut employees = Employees::new();
+        employees.entry("Engineering".to_string()).or_default().push("Sally".to_string());
+        employees.entry("Sales".to_string()).or_default().push("Amir".to_string());
+        employees.entry("Sales".to_string()).or_default().push("Bob".to_string());
+
+        save(&path, &employees).unwrap();
+        assert_eq!(employees, load(&path).unwrap());
+        // The temporary file was renamed over the real one.
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn missing_file_is_empty_and_malformed_line_is_reported() {
+        let dir = TempDir::new("load");
+        assert!(load(&dir.0.join("missing.txt")).unwrap().is_empty());
+
+        let path = dir.0.join("malformed.txt");
+        fs::write(&path, "Sales Amir\nnonsense\n").unwrap();
+        let err = load(&path).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!("line 2: expected '<department> <name>'", err.to_string());
+    }
+}
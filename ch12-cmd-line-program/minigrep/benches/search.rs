@@ -0,0 +1,170 @@
+// Compares minigrep's matchers on generated text.
+//
+//     cargo bench --bench search                  every benchmark, 8 MiB corpora
+//     cargo bench --bench search -- regex         only those named "...regex..."
+//     cargo bench --bench search -- --size 64     on 64 MiB corpora
+//
+// Each benchmark runs until it has taken ten samples or two seconds,
+// whichever comes first, and reports the median. The corpora are made
+// from a fixed seed, so numbers from different runs can be compared.
+
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use minigrep::matcher::Matcher;
+use minigrep::regex::Regex;
+use minigrep::unicode::Folding;
+use minigrep::{search, search_case_insensitive, search_regex, search_with, Count, SearcherBuilder};
+
+const SAMPLES: usize = 10;
+const TIME_LIMIT: Duration = Duration::from_secs(2);
+
+// Common words make up most of the text; the rare ones show up about once
+// every ten thousand lines, which is what a typical search looks for.
+const COMMON: &[&str] = &[
+    "the", "of", "and", "to", "in", "is", "that", "for", "it", "as", "with", "was", "on", "be",
+    "by", "this", "from", "at", "are", "or", "search", "line", "file", "match", "error", "value",
+];
+const RARE: &[&str] = &["zyzzyva", "quartz", "zephyr", "jinx"];
+// Words whose case folding isn't plain ASCII.
+const UNICODE: &[&str] = &["Straße", "ÉCOLE", "naïve", "Ωmega", "İstanbul", "façade"];
+
+struct Corpus {
+    name: &'static str,
+    text: String,
+}
+
+// A xorshift generator: fast, and the same everywhere.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+fn generate(name: &'static str, size: usize, extra: &[&str]) -> Corpus {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let mut text = String::with_capacity(size + 200);
+    while text.len() < size {
+        let words = 5 + rng.below(11);
+        for i in 0..words {
+            if i > 0 {
+                text.push(' ');
+            }
+            let word = match rng.below(100_000) {
+                0..=9 => RARE[rng.below(RARE.len())],
+                10..=999 if !extra.is_empty() => extra[rng.below(extra.len())],
+                _ => COMMON[rng.below(COMMON.len())],
+            };
+            // Capitalize some words, so that case matters.
+            if rng.below(8) == 0 {
+                text.push_str(&word.to_uppercase());
+            } else {
+                text.push_str(word);
+            }
+        }
+        text.push('\n');
+    }
+    Corpus { name, text }
+}
+
+struct Bench {
+    name: &'static str,
+    run: Box<dyn Fn(&str) -> usize>,
+}
+
+fn bench(name: &'static str, run: impl Fn(&str) -> usize + 'static) -> Bench {
+    Bench { name, run: Box::new(run) }
+}
+
+fn benches() -> Vec<Bench> {
+    let ascii = Folding { case: true, ..Default::default() };
+    vec![
+        bench("search, rare word", |text| search("zyzzyva", text).len()),
+        bench("search, common word", |text| search("error", text).len()),
+        bench("search_case_insensitive, rare", |text| search_case_insensitive("zyzzyva", text).len()),
+        bench("search_case_insensitive, unicode", |text| {
+            search_case_insensitive("strasse", text).len()
+        }),
+        bench("search_regex, alternation", {
+            let regex = Regex::new("quartz|zephyr").unwrap();
+            move |text| search_regex(&regex, text).len()
+        }),
+        bench("search_regex, class and repeat", {
+            let regex = Regex::new(r"\bz[a-z]+a\b").unwrap();
+            move |text| search_regex(&regex, text).len()
+        }),
+        bench("literals, 4 patterns", {
+            let matcher = Matcher::literals(RARE);
+            move |text| search_with(&matcher, text).len()
+        }),
+        bench("fuzzy, 1 edit", {
+            let matcher = Matcher::fuzzy(&["zyzzyva"], ascii, 1);
+            move |text| search_with(&matcher, text).len()
+        }),
+        bench("Searcher, streaming count", {
+            let searcher = SearcherBuilder::new("zyzzyva").build().unwrap();
+            move |text| {
+                let mut count = Count::default();
+                searcher.search_reader(text.as_bytes(), &mut count).unwrap();
+                count.0
+            }
+        }),
+    ]
+}
+
+// Runs `bench` on `text` and returns the median time and the number of
+// matches.
+fn measure(bench: &Bench, text: &str) -> (Duration, usize) {
+    let matches = black_box((bench.run)(black_box(text)));
+    let started = Instant::now();
+    let mut samples = Vec::with_capacity(SAMPLES);
+    while samples.len() < SAMPLES && started.elapsed() < TIME_LIMIT {
+        let sample = Instant::now();
+        black_box((bench.run)(black_box(text)));
+        samples.push(sample.elapsed());
+    }
+    samples.sort();
+    (samples[samples.len() / 2], matches)
+}
+
+fn main() {
+    // Cargo passes --bench; anything else not an option is a filter.
+    let mut filter = None;
+    let mut size_mib = 8;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--size" => {
+                let size = args.next().and_then(|size| size.parse().ok());
+                size_mib = size.expect("--size needs a number of MiB");
+            }
+            _ if arg.starts_with("--") => {}
+            _ => filter = Some(arg),
+        }
+    }
+
+    let size = size_mib << 20;
+    let corpora = [generate("ascii", size, &[]), generate("unicode", size, UNICODE)];
+    println!("{:<36}{:<10}{:>12}{:>12}{:>10}", "benchmark", "corpus", "median", "MB/s", "matches");
+
+    for bench in benches() {
+        if filter.as_ref().is_some_and(|filter| !bench.name.contains(filter.as_str())) {
+            continue;
+        }
+        for corpus in &corpora {
+            let (time, matches) = measure(&bench, &corpus.text);
+            let throughput = corpus.text.len() as f64 / time.as_secs_f64() / 1e6;
+            let millis = format!("{:.2} ms", time.as_secs_f64() * 1e3);
+            println!(
+                "{:<36}{:<10}{millis:>12}{throughput:>12.1}{matches:>10}",
+                bench.name, corpus.name
+            );
+        }
+    }
+}
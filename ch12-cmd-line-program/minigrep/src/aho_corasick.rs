@@ -0,0 +1,169 @@
+// Aho-Corasick automaton for finding any of many literal patterns in a
+// single pass over the text.
+//
+// The patterns are stored in a trie, and every state gets a failure link to
+// the longest proper suffix of its path that is also in the trie. Scanning
+// then never backs up: on a mismatch the automaton follows failure links
+// instead of restarting at the next character.
+//
+// It works on any symbol type, so the same code searches raw bytes for
+// exact matches and case-folded chars for -i.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::ops::Range;
+
+pub struct AhoCorasick<T> {
+    states: Vec<State<T>>,
+    // Length of the longest pattern.
+    longest: usize,
+}
+
+struct State<T> {
+    next: HashMap<T, usize>,
+    fail: usize,
+    // Lengths of every pattern that ends in this state, including the ones
+    // reached through failure links, longest first.
+    lengths: Vec<usize>,
+}
+
+impl<T> State<T> {
+    fn new() -> State<T> {
+        State { next: HashMap::new(), fail: 0, lengths: Vec::new() }
+    }
+}
+
+impl<T: Copy + Eq + Hash> AhoCorasick<T> {
+    pub fn new<P: AsRef<[T]>>(patterns: &[P]) -> AhoCorasick<T> {
+        let mut states = vec![State::new()];
+        let mut longest = 0;
+
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            let mut state = 0;
+            for &symbol in pattern {
+                state = match states[state].next.get(&symbol) {
+                    Some(&next) => next,
+                    None => {
+                        states.push(State::new());
+                        let next = states.len() - 1;
+                        states[state].next.insert(symbol, next);
+                        next
+                    }
+                };
+            }
+            states[state].lengths.push(pattern.len());
+            longest = longest.max(pattern.len());
+        }
+
+        // Breadth-first, so a state's failure target is finished before it.
+        let mut queue: VecDeque<usize> = states[0].next.values().copied().collect();
+        while let Some(state) = queue.pop_front() {
+            let edges: Vec<(T, usize)> =
+                states[state].next.iter().map(|(&symbol, &next)| (symbol, next)).collect();
+            for (symbol, next) in edges {
+                let mut fail = states[state].fail;
+                while fail != 0 && !states[fail].next.contains_key(&symbol) {
+                    fail = states[fail].fail;
+                }
+                let fail = states[fail].next.get(&symbol).copied().unwrap_or(0);
+                states[next].fail = fail;
+
+                // The root only ever holds the empty pattern, which is
+                // already tried where each search starts.
+                if fail != 0 {
+                    let inherited = states[fail].lengths.clone();
+                    states[next].lengths.extend(inherited);
+                }
+                queue.push_back(next);
+            }
+            states[state].lengths.sort_unstable_by(|a, b| b.cmp(a));
+            states[state].lengths.dedup();
+        }
+
+        AhoCorasick { states, longest }
+    }
+
+    // Finds the leftmost match starting at or after `from`, preferring the
+    // longest pattern when several start at the same place, like grep.
+    // Candidates that `accept` turns down are skipped. Ranges are indexes
+    // into `haystack`.
+    pub fn find_at(
+        &self,
+        haystack: &[T],
+        from: usize,
+        accept: impl Fn(Range<usize>) -> bool,
+    ) -> Option<Range<usize>> {
+        let mut best: Option<Range<usize>> = None;
+        let consider = |state: usize, end: usize, best: &mut Option<Range<usize>>| {
+            for &length in &self.states[state].lengths {
+                let start = end - length;
+                if best.as_ref().is_some_and(|best| best.start < start) {
+                    break;
+                }
+                if accept(start..end) {
+                    *best = Some(start..end);
+                    break;
+                }
+            }
+        };
+
+        // An empty pattern matches right away.
+        consider(0, from, &mut best);
+
+        let mut state = 0;
+        for (i, symbol) in haystack.iter().enumerate().skip(from) {
+            let end = i + 1;
+            // Anything ending here or later would start after the best match.
+            if best.as_ref().is_some_and(|best| end > best.start + self.longest) {
+                break;
+            }
+            state = self.step(state, symbol);
+            consider(state, end, &mut best);
+        }
+
+        best
+    }
+
+    fn step(&self, mut state: usize, symbol: &T) -> usize {
+        loop {
+            if let Some(&next) = self.states[state].next.get(symbol) {
+                return next;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.states[state].fail;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(patterns: &[&str], haystack: &str) -> Option<Range<usize>> {
+        let ac = AhoCorasick::new(patterns);
+        ac.find_at(haystack.as_bytes(), 0, |_| true)
+    }
+
+    #[test]
+    fn leftmost_longest() {
+        assert_eq!(Some(1..4), find(&["cd", "bcd", "bc"], "abcde"));
+        assert_eq!(Some(1..4), find(&["he", "she", "his", "hers"], "ushers"));
+        assert_eq!(Some(0..3), find(&["abc", "a"], "abc"));
+        assert_eq!(None, find(&["xyz", "zz"], "abcde"));
+        assert_eq!(None, find(&[], "abcde"));
+        assert_eq!(Some(0..0), find(&["", "x"], "abc"));
+    }
+
+    #[test]
+    fn failure_links_and_rejected_candidates() {
+        let ac = AhoCorasick::new(&["aab", "ab"]);
+        assert_eq!(Some(1..4), ac.find_at(b"aaab", 0, |_| true));
+        assert_eq!(Some(5..7), ac.find_at(b"aaab ab", 4, |_| true));
+
+        // Turning down the longer candidate falls back to the shorter one.
+        assert_eq!(Some(2..4), ac.find_at(b"aaab", 0, |range| range.len() == 2));
+    }
+}
@@ -0,0 +1,288 @@
+// Searching inside compressed files and archives.
+//
+// Files are recognized by their first bytes rather than their names. gzip
+// data is decompressed, and whatever comes out of it (or the file itself)
+// is split into members if it is a tar or zip archive. gzip and tar are
+// read as a stream, a member at a time. A zip archive has its directory at
+// the end, so it is read into memory, but its members are still inflated
+// as they are searched.
+
+mod inflate;
+mod tar;
+mod zip;
+
+use std::io::{self, BufRead, BufReader, Chain, Cursor, Read};
+
+pub use inflate::Inflater;
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+// Called with each member's path and contents. Plain compressed files have
+// no path of their own.
+pub type Each<'a> = dyn FnMut(Option<&str>, &mut dyn BufRead) -> io::Result<()> + 'a;
+
+// Whether `start`, the first bytes of a file, looks like something
+// `for_each_member` knows how to open.
+pub fn is_archive(start: &[u8]) -> bool {
+    start.starts_with(GZIP_MAGIC) || zip::is_zip(start) || tar::is_tar(start)
+}
+
+// Reads up to `len` bytes ahead, which a pipe may not return in one go,
+// without losing them. They are at `reader.get_ref().0.get_ref()`.
+pub fn read_ahead<R: Read>(mut reader: R, len: usize) -> io::Result<Chain<Cursor<Vec<u8>>, R>> {
+    let mut start = Vec::with_capacity(len);
+    reader.by_ref().take(len as u64).read_to_end(&mut start)?;
+    Ok(Cursor::new(start).chain(reader))
+}
+
+// Decompresses `reader` and calls `each` on every archive member in turn.
+pub fn for_each_member<R: BufRead>(reader: R, each: &mut Each) -> io::Result<()> {
+    let reader = read_ahead(reader, GZIP_MAGIC.len())?;
+    if reader.get_ref().0.get_ref().starts_with(GZIP_MAGIC) {
+        let decoded = BufReader::new(GzDecoder::new(reader)?);
+        return unpacked_members(decoded, each);
+    }
+    unpacked_members(reader, each)
+}
+
+fn unpacked_members<R: BufRead>(reader: R, each: &mut Each) -> io::Result<()> {
+    let mut reader = read_ahead(reader, tar::BLOCK)?;
+    let start = reader.get_ref().0.get_ref();
+
+    if zip::is_zip(start) {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        zip::for_each_member(&data, each)
+    } else if tar::is_tar(start) {
+        tar::for_each_member(reader, each)
+    } else {
+        each(None, &mut reader)
+    }
+}
+
+// Decompresses gzip data, which may hold several members back to back.
+pub struct GzDecoder<R> {
+    inflater: Inflater<R>,
+    crc: u32,
+    size: u32,
+    done: bool,
+}
+
+impl<R: BufRead> GzDecoder<R> {
+    pub fn new(mut reader: R) -> io::Result<GzDecoder<R>> {
+        skip_gzip_header(&mut reader)?;
+        Ok(GzDecoder { inflater: Inflater::new(reader), crc: 0, size: 0, done: false })
+    }
+
+    // Checks the trailer of the member just inflated, and starts on the
+    // next one if there is one.
+    fn next_member(&mut self) -> io::Result<()> {
+        let input = self.inflater.get_mut();
+        let mut trailer = [0; 8];
+        input.read_exact(&mut trailer).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => invalid("truncated gzip trailer"),
+            _ => err,
+        })?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc != self.crc || size != self.size {
+            return Err(invalid("gzip checksum mismatch"));
+        }
+
+        // Anything after the last member that isn't another one is ignored.
+        let input = read_ahead(input, GZIP_MAGIC.len())?;
+        if !input.get_ref().0.get_ref().starts_with(GZIP_MAGIC) {
+            self.done = true;
+            return Ok(());
+        }
+        skip_gzip_header(input)?;
+        self.inflater.reset();
+        self.crc = 0;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.done {
+            let n = self.inflater.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.crc = crc32_update(self.crc, &buf[..n]);
+                self.size = self.size.wrapping_add(n as u32);
+                return Ok(n);
+            }
+            self.next_member()?;
+        }
+        Ok(0)
+    }
+}
+
+fn skip_gzip_header<R: BufRead>(mut reader: R) -> io::Result<()> {
+    const FHCRC: u8 = 2;
+    const FEXTRA: u8 = 4;
+    const FNAME: u8 = 8;
+    const FCOMMENT: u8 = 16;
+
+    let truncated = |err: io::Error| match err.kind() {
+        io::ErrorKind::UnexpectedEof => invalid("truncated gzip header"),
+        _ => err,
+    };
+    let mut header = [0; 10];
+    reader.read_exact(&mut header).map_err(truncated)?;
+    if !header.starts_with(GZIP_MAGIC) || header[2] != 8 {
+        return Err(invalid("unsupported gzip header"));
+    }
+    let flags = header[3];
+
+    if flags & FEXTRA != 0 {
+        let mut len = [0; 2];
+        reader.read_exact(&mut len).map_err(truncated)?;
+        let len = u64::from(u16::from_le_bytes(len));
+        if io::copy(&mut reader.by_ref().take(len), &mut io::sink())? < len {
+            return Err(invalid("truncated gzip header"));
+        }
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 && reader.skip_until(0)? == 0 {
+            return Err(invalid("truncated gzip header"));
+        }
+    }
+    if flags & FHCRC != 0 {
+        reader.read_exact(&mut [0; 2]).map_err(truncated)?;
+    }
+    Ok(())
+}
+
+// Checks the CRC-32 of what `inner` reads once it reaches the end.
+struct Checked<R> {
+    inner: R,
+    crc: u32,
+    expected: u32,
+}
+
+impl<R: Read> Read for Checked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc = crc32_update(self.crc, &buf[..n]);
+        if n == 0 && !buf.is_empty() && self.crc != self.expected {
+            return Err(invalid("zip checksum mismatch"));
+        }
+        Ok(n)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// The CRC-32 used by gzip and zip.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+// Carries `crc`, the CRC-32 of what came before, on over `data`.
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gunzip(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        GzDecoder::new(data)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    // The inflated data and how much of the input it took.
+    fn inflate(data: &[u8]) -> io::Result<(Vec<u8>, usize)> {
+        let mut inflater = Inflater::new(data);
+        let mut out = Vec::new();
+        inflater.read_to_end(&mut out)?;
+        Ok((out, data.len() - inflater.get_mut().len()))
+    }
+
+    const GZIPPED: &[u8] = b"\
+        \x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x4b\xcb\x2c\x2a\x2e\x51\xc8\xc9\xcc\x4b\
+        \xe5\x72\x0d\x0a\xf2\x0f\x52\x48\xc9\x2c\xce\x56\x48\x2b\xcd\xc9\xe1\xca\x49\x84\
+        \xc9\x00\x00\x06\xe9\x2e\xf4\x25\x00\x00\x00";
+
+    #[test]
+    fn gzip_members_concatenate() {
+        let text = b"first line\nERROR disk full\nlast line\n";
+        assert_eq!(text.to_vec(), gunzip(GZIPPED).unwrap());
+
+        let twice = [GZIPPED, GZIPPED].concat();
+        assert_eq!([&text[..], &text[..]].concat(), gunzip(&twice).unwrap());
+
+        let mut corrupted = GZIPPED.to_vec();
+        corrupted[45] ^= 1;
+        assert!(gunzip(&corrupted).is_err());
+    }
+
+    #[test]
+    fn inflates_every_block_type() {
+        let stored = b"\x01\x0d\x00\xf2\xff\x73\x74\x6f\x72\x65\x64\x20\x45\x52\x52\x4f\x52\x0a";
+        assert_eq!((b"stored ERROR\n".to_vec(), stored.len()), inflate(stored).unwrap());
+        let trailing = [&stored[..], b"after"].concat();
+        assert_eq!((b"stored ERROR\n".to_vec(), stored.len()), inflate(&trailing).unwrap());
+
+        // 40 numbered lines compressed with a dynamic Huffman block.
+        let dynamic = b"\
+            \x9d\xd5\x5b\x16\xc1\x50\x0c\x46\xe1\x77\xa3\xc8\x10\xe4\x0f\x2d\x66\xe3\x72\x68\
+            \x39\x7a\x68\xd5\x6d\xf4\x16\x33\xb0\x9f\xb3\xf6\x53\xbe\x95\xe4\xb6\x4b\x36\x5d\
+            \xd9\xad\x49\x76\x1d\xdb\xed\xc9\x36\x7d\x79\x74\xb6\x2f\x4f\x3b\x8e\xe7\xcb\x60\
+            \xe5\x9e\xfa\xdf\x38\xaf\xdf\x2f\xdb\x95\xc3\x24\x7f\x1b\x07\x8d\x40\x13\xa0\x99\
+            \x81\x66\x0e\x9a\x0a\x34\x35\x68\x16\xa0\x59\x92\x9d\x22\x08\x44\x82\x13\x0a\x4e\
+            \x2c\x38\xc1\xe0\x44\x83\x13\x0e\x4e\x3c\x38\x01\xe1\x44\x84\x88\x08\xa1\xdb\x40\
+            \x44\x88\x88\x10\x11\x21\x22\x42\x44\x84\x88\x08\x11\x11\x22\x22\x82\x88\x08\x22\
+            \x22\xd0\xbb\x20\x22\x82\x88\x08\x22\x22\x88\x88\x20\x22\x82\x88\x88\x3f\x45\x7c\
+            \x00";
+        let expected: String = (0..40)
+            .map(|i| format!("line {i}: the quick brown fox jumps over the lazy dog\n"))
+            .collect();
+        assert_eq!(expected.into_bytes(), inflate(dynamic).unwrap().0);
+
+        assert!(inflate(b"\x07").is_err());
+    }
+
+    #[test]
+    fn stops_at_decompression_bombs() {
+        // 64 KiB of zeros, flushed so that copies can follow one another.
+        let mut zeros = b"\xec\xc1\x01\x01\x00\x00\x00\x80\x90\xfe\xaf\xee\x08\x0a".to_vec();
+        zeros.resize(77, 0);
+        zeros.extend_from_slice(b"\x6a\x00\x00\x00\xff\xff");
+        let end = b"\x03\x00";
+
+        let small = [zeros.repeat(4), end.to_vec()].concat();
+        assert_eq!(vec![0; 4 << 16], inflate(&small).unwrap().0);
+
+        let bomb = [zeros.repeat(1100), end.to_vec()].concat();
+        let err = inflate(&bomb).unwrap_err();
+        assert!(err.to_string().contains("decompression bomb"));
+    }
+
+    #[test]
+    fn plain_files_are_one_member() {
+        let mut members = Vec::new();
+        for_each_member(GZIPPED, &mut |path, contents| {
+            let mut data = Vec::new();
+            contents.read_to_end(&mut data)?;
+            members.push((path.map(str::to_string), data));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(vec![(None, gunzip(GZIPPED).unwrap())], members);
+        assert!(!is_archive(b"plain text"));
+    }
+}
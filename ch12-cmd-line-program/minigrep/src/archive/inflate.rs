@@ -0,0 +1,362 @@
+// A DEFLATE decoder (RFC 1951), as used inside gzip and zip files.
+//
+// Huffman codes are decoded one bit at a time using the canonical code
+// layout: for each code length, the codes of that length are consecutive
+// numbers, so counting how many symbols have each length is enough to
+// decode without building a lookup table.
+
+use std::io::{self, BufRead, Read};
+
+const MAX_BITS: usize = 15;
+
+// Base values and extra bits for length codes 257..285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// Base values and extra bits for distance codes 0..29.
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// The order in which code length code lengths are stored in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] =
+    [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// Deflate can't shrink anything by much more than 1000 to 1, and real
+// text rarely comes close to MAX_RATIO, so output past RATIO_FREE that
+// keeps growing faster than that is taken for a decompression bomb.
+const MAX_RATIO: u64 = 500;
+const RATIO_FREE: u64 = 64 << 20;
+
+// How far back a match can reach.
+const WINDOW_SIZE: usize = 32 * 1024;
+
+// Decompresses a raw DEFLATE stream as it is read. Once `read` returns 0,
+// the input is positioned just past the end of the stream, so callers can
+// find what follows it.
+pub struct Inflater<R> {
+    bits: Bits<R>,
+    block: Block,
+    // Whether the current block is the last of the stream.
+    last: bool,
+    done: bool,
+    // Bytes still to copy from earlier output, and how far back they are.
+    copy: usize,
+    distance: usize,
+    // The last WINDOW_SIZE bytes of output, indexed by `out` modulo its size.
+    window: Vec<u8>,
+    // Output of the current stream so far.
+    out: u64,
+    // Output of every stream so far, for the ratio check.
+    total: u64,
+}
+
+enum Block {
+    // Between blocks.
+    None,
+    // Bytes left in a stored block.
+    Stored(usize),
+    // Literal/length and distance codes.
+    Codes(Huffman, Huffman),
+}
+
+impl<R: BufRead> Inflater<R> {
+    pub fn new(input: R) -> Inflater<R> {
+        Inflater {
+            bits: Bits { input, buffer: 0, count: 0, consumed: 0 },
+            block: Block::None,
+            last: false,
+            done: false,
+            copy: 0,
+            distance: 0,
+            window: vec![0; WINDOW_SIZE],
+            out: 0,
+            total: 0,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.bits.input
+    }
+
+    // Starts on another stream, which follows in the input.
+    pub fn reset(&mut self) {
+        self.bits.align();
+        self.block = Block::None;
+        self.last = false;
+        self.done = false;
+        self.copy = 0;
+        self.out = 0;
+    }
+
+    fn start_block(&mut self) -> io::Result<()> {
+        self.last = self.bits.take(1)? == 1;
+        self.block = match self.bits.take(2)? {
+            0 => {
+                self.bits.align();
+                let mut header = [0; 4];
+                for byte in &mut header {
+                    *byte = self.bits.byte()?;
+                }
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let complement = u16::from_le_bytes([header[2], header[3]]);
+                if len != !complement {
+                    return Err(corrupt("stored block length mismatch"));
+                }
+                Block::Stored(usize::from(len))
+            }
+            1 => {
+                let (literals, distances) = fixed_codes();
+                Block::Codes(literals, distances)
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut self.bits)?;
+                Block::Codes(literals, distances)
+            }
+            _ => return Err(corrupt("invalid block type")),
+        };
+        Ok(())
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.window[self.out as usize % WINDOW_SIZE] = byte;
+        self.out += 1;
+    }
+}
+
+impl<R: BufRead> Read for Inflater<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() && !self.done {
+            if self.copy > 0 {
+                let from = (self.out as usize).wrapping_sub(self.distance);
+                let byte = self.window[from % WINDOW_SIZE];
+                self.push(byte);
+                buf[n] = byte;
+                n += 1;
+                self.copy -= 1;
+                continue;
+            }
+
+            match &mut self.block {
+                Block::None if self.last => self.done = true,
+                Block::None => self.start_block()?,
+                Block::Stored(0) => self.block = Block::None,
+                Block::Stored(left) => {
+                    let available = self.bits.input.fill_buf()?;
+                    if available.is_empty() {
+                        return Err(corrupt("unexpected end"));
+                    }
+                    let len = available.len().min(*left).min(buf.len() - n);
+                    buf[n..n + len].copy_from_slice(&available[..len]);
+                    *left -= len;
+                    self.bits.input.consume(len);
+                    self.bits.consumed += len as u64;
+                    for &byte in &buf[n..n + len] {
+                        self.push(byte);
+                    }
+                    n += len;
+                }
+                Block::Codes(literals, distances) => {
+                    let symbol = usize::from(literals.decode(&mut self.bits)?);
+                    match symbol {
+                        0..=255 => {
+                            self.push(symbol as u8);
+                            buf[n] = symbol as u8;
+                            n += 1;
+                        }
+                        256 => self.block = Block::None,
+                        257..=285 => {
+                            let i = symbol - 257;
+                            let extra = self.bits.take(u32::from(LENGTH_EXTRA[i]))? as usize;
+                            let length = usize::from(LENGTH_BASE[i]) + extra;
+
+                            let i = usize::from(distances.decode(&mut self.bits)?);
+                            if i >= DIST_BASE.len() {
+                                return Err(corrupt("invalid distance code"));
+                            }
+                            let extra = self.bits.take(u32::from(DIST_EXTRA[i]))? as usize;
+                            let distance = usize::from(DIST_BASE[i]) + extra;
+                            if distance as u64 > self.out {
+                                return Err(corrupt("distance too far back"));
+                            }
+                            // Copied a byte at a time above, since the
+                            // source and destination can overlap.
+                            self.copy = length;
+                            self.distance = distance;
+                        }
+                        _ => return Err(corrupt("invalid literal/length code")),
+                    }
+                }
+            }
+        }
+
+        self.total += n as u64;
+        if self.total > RATIO_FREE && self.total / MAX_RATIO > self.bits.consumed {
+            let message = format!(
+                "data decompresses to over {MAX_RATIO} times its size, \
+                 which looks like a decompression bomb"
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        Ok(n)
+    }
+}
+
+fn corrupt(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("corrupt deflate data: {message}"))
+}
+
+struct Bits<R> {
+    input: R,
+    buffer: u32,
+    count: u32,
+    // Bytes taken from the input.
+    consumed: u64,
+}
+
+impl<R: BufRead> Bits<R> {
+    // Takes `n` bits, least significant first.
+    fn take(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            self.buffer |= u32::from(self.byte()?) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = *self.input.fill_buf()?.first().ok_or_else(|| corrupt("unexpected end"))?;
+        self.input.consume(1);
+        self.consumed += 1;
+        Ok(byte)
+    }
+
+    // Drops the bits left in the current byte. Bits are only loaded a
+    // byte at a time as they are needed, so that's all there is.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+struct Huffman {
+    // Number of codes of each length.
+    counts: [u16; MAX_BITS + 1],
+    // Symbols ordered by code.
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+
+        // Reject codes that use more bit patterns than exist.
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = left * 2 - i32::from(count);
+            if left < 0 {
+                return Err(corrupt("oversubscribed code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; usize::from(offsets[MAX_BITS + 1])];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                let offset = &mut offsets[usize::from(length)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode<R: BufRead>(&self, bits: &mut Bits<R>) -> io::Result<u16> {
+        // First code of the current length, and the index of its symbol.
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=MAX_BITS {
+            code |= bits.take(1)? as i32;
+            let count = i32::from(self.counts[length]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(corrupt("invalid code"))
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literals = Huffman::new(&lengths).expect("fixed literal code is valid");
+    let distances = Huffman::new(&[5; 30]).expect("fixed distance code is valid");
+    (literals, distances)
+}
+
+fn dynamic_codes<R: BufRead>(bits: &mut Bits<R>) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = bits.take(5)? as usize + 257;
+    let distance_count = bits.take(5)? as usize + 1;
+    let length_count = bits.take(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(corrupt("too many codes"));
+    }
+
+    let mut length_lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..length_count] {
+        length_lengths[i] = bits.take(3)? as u8;
+    }
+    let length_code = Huffman::new(&length_lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = length_code.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or_else(|| corrupt("repeat with no length"))?;
+                (previous, 3 + bits.take(2)?)
+            }
+            17 => (0, 3 + bits.take(3)?),
+            _ => (0, 11 + bits.take(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(corrupt("too many code lengths"));
+    }
+    if lengths[256] == 0 {
+        return Err(corrupt("no end-of-block code"));
+    }
+
+    let literals = Huffman::new(&lengths[..literal_count])?;
+    let distances = Huffman::new(&lengths[literal_count..])?;
+    Ok((literals, distances))
+}
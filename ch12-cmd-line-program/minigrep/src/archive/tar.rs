@@ -0,0 +1,165 @@
+// Reading regular files out of ustar archives, including GNU long names
+// and the path field of pax extended headers.
+
+use std::io::{self, BufRead, Read};
+
+use super::{invalid, Each};
+
+pub const BLOCK: usize = 512;
+
+// The most a long name or pax header may hold, as they are read whole.
+const MAX_HEADER_DATA: u64 = 1 << 20;
+
+pub fn is_tar(data: &[u8]) -> bool {
+    data.get(257..262) == Some(b"ustar")
+}
+
+pub fn for_each_member<R: BufRead>(mut reader: R, each: &mut Each) -> io::Result<()> {
+    // A name given by the entry before, for the next file.
+    let mut long_name = None;
+    let mut header = [0; BLOCK];
+
+    loop {
+        // The archive ends with zeroed blocks, though tar itself doesn't
+        // mind if they are missing.
+        match reader.read_exact(&mut header) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            result => result?,
+        }
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+
+        let size = number(&header[124..136])?;
+        let mut contents = reader.by_ref().take(size);
+
+        match header[156] {
+            // GNU long name for the next entry.
+            b'L' => long_name = Some(text(&header_data(&mut contents)?)),
+            // pax extended header, of which only the path matters.
+            b'x' => long_name = pax_path(&header_data(&mut contents)?).or(long_name),
+            b'0' | b'\0' | b'7' => {
+                let path = long_name.take().unwrap_or_else(|| {
+                    let name = text(&header[..100]);
+                    match text(&header[345..500]) {
+                        prefix if prefix.is_empty() => name,
+                        prefix => format!("{prefix}/{name}"),
+                    }
+                });
+                each(Some(&path), &mut contents)?;
+            }
+            // Directories, links and devices have nothing to search.
+            _ => long_name = None,
+        }
+
+        // Whatever wasn't read, then the padding to the next block.
+        io::copy(&mut contents, &mut io::sink())?;
+        if contents.limit() > 0 {
+            return Err(invalid("truncated tar entry"));
+        }
+        let padding = (BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64;
+        io::copy(&mut reader.by_ref().take(padding), &mut io::sink())?;
+    }
+
+    Ok(())
+}
+
+fn header_data(contents: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    contents.take(MAX_HEADER_DATA + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_HEADER_DATA {
+        return Err(invalid("tar extended header too long"));
+    }
+    Ok(data)
+}
+
+// A NUL-terminated string field.
+fn text(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+// An octal number field, or a base-256 one if its top bit is set.
+fn number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        let value = field[1..].iter().fold(0u64, |n, &b| (n << 8) | u64::from(b));
+        return Ok(value);
+    }
+    let digits = text(field);
+    let digits = digits.trim_matches(' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid("invalid tar header"))
+}
+
+// Records look like "30 path=some/long/file/name\n".
+fn pax_path(contents: &[u8]) -> Option<String> {
+    let contents = String::from_utf8_lossy(contents);
+    contents.lines().find_map(|record| {
+        let (_, field) = record.split_once(' ')?;
+        field.strip_prefix("path=").map(str::to_string)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(archive: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let mut members = Vec::new();
+        for_each_member(archive, &mut |path, contents| {
+            let mut data = Vec::new();
+            contents.read_to_end(&mut data)?;
+            members.push((path.unwrap().to_string(), data));
+            Ok(())
+        })?;
+        Ok(members)
+    }
+
+    // Builds a tar entry: a header block followed by the padded contents.
+    fn entry(name: &str, kind: u8, contents: &[u8]) -> Vec<u8> {
+        let mut header = vec![0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+
+        let mut entry = header;
+        entry.extend_from_slice(contents);
+        entry.resize(BLOCK + contents.len().div_ceil(BLOCK) * BLOCK, 0);
+        entry
+    }
+
+    #[test]
+    fn reads_files_and_long_names() {
+        let long = format!("{}/deep.log", "nested".repeat(20));
+        let archive = [
+            entry("logs/", b'5', b""),
+            entry("logs/app.log", b'0', b"ERROR one\n"),
+            entry("././@LongLink", b'L', long.as_bytes()),
+            entry("truncated", b'0', b"ERROR two\n"),
+            vec![0; BLOCK * 2],
+        ]
+        .concat();
+
+        assert!(is_tar(&archive));
+        assert_eq!(
+            vec![
+                ("logs/app.log".to_string(), b"ERROR one\n".to_vec()),
+                (long, b"ERROR two\n".to_vec()),
+            ],
+            members(&archive).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_sizes_past_the_end() {
+        let mut archive = entry("huge.log", b'0', b"");
+        // A base-256 size of u64::MAX.
+        archive[124] = 0x80;
+        archive[125..136].fill(0xff);
+        let err = members(&archive).unwrap_err();
+        assert_eq!("truncated tar entry", err.to_string());
+    }
+}
@@ -0,0 +1,134 @@
+// Reading files out of zip archives through their central directory.
+// Members may be stored or deflated; zip64 and encrypted archives are not
+// supported.
+
+use std::io::{self, BufReader, Read};
+
+use super::{invalid, Checked, Each, Inflater};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06")
+}
+
+pub fn for_each_member(data: &[u8], each: &mut Each) -> io::Result<()> {
+    let end = end_of_directory(data).ok_or_else(|| invalid("zip directory not found"))?;
+    let count = usize::from(u16_at(data, end + 10)?);
+    let mut pos = u32_at(data, end + 16)? as usize;
+
+    for _ in 0..count {
+        if u32_at(data, pos)? != CENTRAL_HEADER {
+            return Err(invalid("corrupt zip directory"));
+        }
+        let flags = u16_at(data, pos + 8)?;
+        let method = u16_at(data, pos + 10)?;
+        let crc = u32_at(data, pos + 16)?;
+        let compressed = u32_at(data, pos + 20)? as usize;
+        let name_len = usize::from(u16_at(data, pos + 28)?);
+        let extra_len = usize::from(u16_at(data, pos + 30)?);
+        let comment_len = usize::from(u16_at(data, pos + 32)?);
+        let offset = u32_at(data, pos + 42)? as usize;
+        let name = bytes(data, pos + 46, name_len)?;
+        let path = String::from_utf8_lossy(name).into_owned();
+        pos += 46 + name_len + extra_len + comment_len;
+
+        if path.ends_with('/') {
+            continue;
+        }
+        if flags & 1 != 0 {
+            return Err(invalid("encrypted zip members are not supported"));
+        }
+
+        if u32_at(data, offset)? != LOCAL_HEADER {
+            return Err(invalid("corrupt zip entry"));
+        }
+        let start = offset + 30 + usize::from(u16_at(data, offset + 26)?) + usize::from(u16_at(data, offset + 28)?);
+        let raw = bytes(data, start, compressed)?;
+        let inner: Box<dyn Read> = match method {
+            0 => Box::new(raw),
+            8 => Box::new(Inflater::new(raw)),
+            _ => return Err(invalid("unsupported zip compression method")),
+        };
+        let mut contents = BufReader::new(Checked { inner, crc: 0, expected: crc });
+        each(Some(&path), &mut contents)?;
+        // Reading to the end checks the CRC, even of what wasn't searched.
+        io::copy(&mut contents, &mut io::sink())?;
+    }
+
+    Ok(())
+}
+
+// The end of central directory record sits at the very end, followed only
+// by an optional comment of up to 64 KiB.
+fn end_of_directory(data: &[u8]) -> Option<usize> {
+    let last = data.len().checked_sub(22)?;
+    let first = last.saturating_sub(u16::MAX as usize);
+    (first..=last).rev().find(|&pos| u32_at(data, pos).ok() == Some(END_OF_DIRECTORY))
+}
+
+fn bytes(data: &[u8], pos: usize, len: usize) -> io::Result<&[u8]> {
+    data.get(pos..pos + len).ok_or_else(|| invalid("truncated zip archive"))
+}
+
+fn u16_at(data: &[u8], pos: usize) -> io::Result<u16> {
+    let b = bytes(data, pos, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> io::Result<u32> {
+    let b = bytes(data, pos, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(archive: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let mut members = Vec::new();
+        for_each_member(archive, &mut |path, contents| {
+            let mut data = Vec::new();
+            contents.read_to_end(&mut data)?;
+            members.push((path.unwrap().to_string(), data));
+            Ok(())
+        })?;
+        Ok(members)
+    }
+
+    // docs/readme.txt (deflated) and notes.txt (stored), made with Python's zipfile.
+    const ARCHIVE: &[u8] = b"\
+        \x50\x4b\x03\x04\x14\x00\x00\x00\x08\x00\x00\x00\x21\x50\x54\x5c\x6b\x91\x16\x00\
+        \x00\x00\x14\x00\x00\x00\x0f\x00\x00\x00\x64\x6f\x63\x73\x2f\x72\x65\x61\x64\x6d\
+        \x65\x2e\x74\x78\x74\xcb\x48\xcd\xc9\xc9\xe7\x72\x0d\x0a\xf2\x0f\x52\xc8\xcc\x53\
+        \x48\xc9\x4f\x2e\xe6\x02\x00\x50\x4b\x03\x04\x14\x00\x00\x00\x00\x00\x00\x00\x21\
+        \x50\x5b\xd0\x18\xad\x0d\x00\x00\x00\x0d\x00\x00\x00\x09\x00\x00\x00\x6e\x6f\x74\
+        \x65\x73\x2e\x74\x78\x74\x45\x52\x52\x4f\x52\x20\x73\x74\x6f\x72\x65\x64\x0a\x50\
+        \x4b\x01\x02\x14\x03\x14\x00\x00\x00\x08\x00\x00\x00\x21\x50\x54\x5c\x6b\x91\x16\
+        \x00\x00\x00\x14\x00\x00\x00\x0f\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x80\
+        \x01\x00\x00\x00\x00\x64\x6f\x63\x73\x2f\x72\x65\x61\x64\x6d\x65\x2e\x74\x78\x74\
+        \x50\x4b\x01\x02\x14\x03\x14\x00\x00\x00\x00\x00\x00\x00\x21\x50\x5b\xd0\x18\xad\
+        \x0d\x00\x00\x00\x0d\x00\x00\x00\x09\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\
+        \x80\x01\x43\x00\x00\x00\x6e\x6f\x74\x65\x73\x2e\x74\x78\x74\x50\x4b\x05\x06\x00\
+        \x00\x00\x00\x02\x00\x02\x00\x74\x00\x00\x00\x77\x00\x00\x00\x00\x00";
+
+    #[test]
+    fn reads_stored_and_deflated_members() {
+        assert!(is_zip(ARCHIVE));
+        assert_eq!(
+            vec![
+                ("docs/readme.txt".to_string(), b"hello\nERROR in docs\n".to_vec()),
+                ("notes.txt".to_string(), b"ERROR stored\n".to_vec()),
+            ],
+            members(ARCHIVE).unwrap()
+        );
+        assert!(members(&ARCHIVE[..ARCHIVE.len() - 30]).is_err());
+
+        let mut corrupted = ARCHIVE.to_vec();
+        let at = ARCHIVE.windows(12).position(|w| w == b"ERROR stored").unwrap();
+        corrupted[at] ^= 1;
+        assert_eq!("zip checksum mismatch", members(&corrupted).unwrap_err().to_string());
+    }
+}
@@ -0,0 +1,1280 @@
+// Command-line parsing for minigrep.
+//
+// Every option is described once in the OPTIONS table. The parser and the
+// --help text are both driven by that table, so adding an option means
+// adding a row here and a match arm in Config::apply.
+//
+// Options are gathered from three places, each overriding the one before:
+// the IGNORE_CASE environment variable, the config file, and the command
+// line. The config file is $MINIGREP_CONFIG_PATH, or ~/.minigreprc if that
+// isn't set, and holds one option per line:
+//
+//   # Lines starting with '#' are comments.
+//   --line-number
+//   --context 2
+//   --type-add=web:*.html,*.css
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use crate::encoding::Encoding;
+use crate::matcher::Boundary;
+use crate::output::{Mode, PrintOptions};
+use crate::unicode::Normalization;
+use crate::ReadOptions;
+
+const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY [PATH]...
+       minigrep [OPTIONS] (-e QUERY | -f FILE)... [PATH]...
+       minigrep index build DIR...
+
+Searches each PATH, or standard input if PATH is - or missing.
+Directories are searched recursively. With -e or -f, every argument is
+a PATH and a line matches if it matches any of the patterns.
+
+With -E, -i folds case one character at a time, except that a character
+of QUERY that folds to several also matches them: -E -i 'straße' matches
+STRASSE, but -E -i 'strasse' doesn't match Straße as -i alone does.
+
+--interactive opens the results in a full-screen browser. It has no
+short form, since -i stays --ignore-case as in grep.
+
+Default options are read from $MINIGREP_CONFIG_PATH, or ~/.minigreprc,
+one per line. Options on the command line override them.
+
+'minigrep index build' creates or updates a trigram index of each DIR,
+which searches with --index use to skip files that can't match.";
+
+struct Opt {
+    short: Option<char>,
+    long: &'static str,
+    // Name of the value shown in --help, or None for a plain flag.
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+const OPTIONS: &[Opt] = &[
+    Opt {
+        short: Some('e'),
+        long: "regexp",
+        value: Some("QUERY"),
+        help: "Search for QUERY (repeatable)",
+    },
+    Opt {
+        short: Some('f'),
+        long: "file",
+        value: Some("FILE"),
+        help: "Read queries from FILE, one per line (- for stdin)",
+    },
+    Opt {
+        short: Some('i'),
+        long: "ignore-case",
+        value: None,
+        help: "Ignore case, using full Unicode case folding (default when IGNORE_CASE is set)",
+    },
+    Opt {
+        short: None,
+        long: "no-ignore-case",
+        value: None,
+        help: "Match case exactly, even if IGNORE_CASE is set",
+    },
+    Opt {
+        short: None,
+        long: "turkic",
+        value: None,
+        help: "With -i, fold dotted and dotless i the Turkish way",
+    },
+    Opt {
+        short: None,
+        long: "normalize",
+        value: Some("FORM"),
+        help: "Match canonically equivalent text: nfc or nfd",
+    },
+    Opt {
+        short: Some('E'),
+        long: "regex",
+        value: None,
+        help: "Treat QUERY as a regular expression",
+    },
+    Opt {
+        short: Some('w'),
+        long: "word-regexp",
+        value: None,
+        help: "Only match whole words",
+    },
+    Opt {
+        short: Some('x'),
+        long: "line-regexp",
+        value: None,
+        help: "Only match whole lines",
+    },
+    Opt {
+        short: None,
+        long: "fuzzy",
+        value: Some("EDITS"),
+        help: "Also match text up to EDITS insertions, deletions or substitutions away",
+    },
+    Opt {
+        short: None,
+        long: "query",
+        value: Some("EXPR"),
+        help: "Select lines by a boolean EXPR of terms, AND, OR, NOT and ( ), instead of QUERY",
+    },
+    Opt {
+        short: None,
+        long: "replace",
+        value: Some("REPL"),
+        help: "Print lines with every match replaced by REPL ($1 for groups)",
+    },
+    Opt {
+        short: None,
+        long: "in-place",
+        value: None,
+        help: "With --replace, rewrite the files instead of printing",
+    },
+    Opt {
+        short: None,
+        long: "dry-run",
+        value: None,
+        help: "With --replace, print a diff of what --in-place would change",
+    },
+    Opt {
+        short: Some('U'),
+        long: "multiline",
+        value: None,
+        help: "Let matches span several lines; files are read into memory whole",
+    },
+    Opt {
+        short: Some('z'),
+        long: "null-data",
+        value: None,
+        help: "Input and output lines end with NUL instead of a newline",
+    },
+    Opt {
+        short: Some('v'),
+        long: "invert-match",
+        value: None,
+        help: "Select lines that do not match",
+    },
+    Opt {
+        short: Some('c'),
+        long: "count",
+        value: None,
+        help: "Print only the number of matching lines per file",
+    },
+    Opt {
+        short: Some('l'),
+        long: "files-with-matches",
+        value: None,
+        help: "Print only the names of files with matches",
+    },
+    Opt {
+        short: Some('L'),
+        long: "files-without-match",
+        value: None,
+        help: "Print only the names of files without matches",
+    },
+    Opt {
+        short: Some('m'),
+        long: "max-count",
+        value: Some("NUM"),
+        help: "Stop reading a file after NUM matching lines",
+    },
+    Opt {
+        short: Some('n'),
+        long: "line-number",
+        value: None,
+        help: "Prefix each line with its line number",
+    },
+    Opt {
+        short: Some('b'),
+        long: "byte-offset",
+        value: None,
+        help: "Prefix each line with its byte offset",
+    },
+    Opt {
+        short: Some('A'),
+        long: "after-context",
+        value: Some("NUM"),
+        help: "Print NUM lines of trailing context",
+    },
+    Opt {
+        short: Some('B'),
+        long: "before-context",
+        value: Some("NUM"),
+        help: "Print NUM lines of leading context",
+    },
+    Opt {
+        short: Some('C'),
+        long: "context",
+        value: Some("NUM"),
+        help: "Print NUM lines of leading and trailing context",
+    },
+    Opt {
+        short: None,
+        long: "color",
+        value: Some("WHEN"),
+        help: "Highlight matches: auto, always or never (default: auto)",
+    },
+    Opt {
+        short: None,
+        long: "json",
+        value: None,
+        help: "Print results as JSON, one object per line",
+    },
+    Opt {
+        short: None,
+        long: "encoding",
+        value: Some("ENC"),
+        help: "Read files without a byte order mark as utf-8, utf-16le, utf-16be, latin1 or windows-1252",
+    },
+    Opt {
+        short: None,
+        long: "include",
+        value: Some("GLOB"),
+        help: "Only search files matching GLOB (repeatable)",
+    },
+    Opt {
+        short: None,
+        long: "exclude",
+        value: Some("GLOB"),
+        help: "Skip files and directories matching GLOB (repeatable)",
+    },
+    Opt {
+        short: Some('t'),
+        long: "type",
+        value: Some("TYPE"),
+        help: "Only search files of TYPE, such as rust or py (repeatable)",
+    },
+    Opt {
+        short: None,
+        long: "type-add",
+        value: Some("TYPE:GLOB"),
+        help: "Define TYPE as files matching GLOB; commas separate several globs",
+    },
+    Opt {
+        short: None,
+        long: "hidden",
+        value: None,
+        help: "Search hidden files and directories",
+    },
+    Opt {
+        short: None,
+        long: "no-ignore",
+        value: None,
+        help: "Don't honor .gitignore, .ignore or git's global excludes",
+    },
+    Opt {
+        short: None,
+        long: "index",
+        value: None,
+        help: "Skip files that the index of a searched directory rules out",
+    },
+    Opt {
+        short: None,
+        long: "follow",
+        value: None,
+        help: "Keep reading each FILE as it grows, like tail -f, and print new matches",
+    },
+    Opt {
+        short: None,
+        long: "watch",
+        value: None,
+        help: "After searching, search files again whenever they change",
+    },
+    Opt {
+        short: None,
+        long: "interactive",
+        value: None,
+        help: "Browse results full-screen, editing QUERY as you go (-i stays --ignore-case)",
+    },
+    Opt {
+        short: None,
+        long: "stats",
+        value: None,
+        help: "Print how much was searched and where the time went",
+    },
+    Opt {
+        short: Some('j'),
+        long: "threads",
+        value: Some("NUM"),
+        help: "Search NUM files at once (default: one per CPU)",
+    },
+    Opt {
+        short: None,
+        long: "no-config",
+        value: None,
+        help: "Don't read the config file",
+    },
+    Opt {
+        short: None,
+        long: "debug-config",
+        value: None,
+        help: "Print where each setting came from to stderr",
+    },
+    Opt {
+        short: Some('h'),
+        long: "help",
+        value: None,
+        help: "Print help and exit",
+    },
+    Opt {
+        short: Some('V'),
+        long: "version",
+        value: None,
+        help: "Print version and exit",
+    },
+];
+
+// Globs for the file types that --type knows without a --type-add.
+const TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.h"]),
+    ("css", &["*.css"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.cjs", "*.jsx"]),
+    ("json", &["*.json"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    NotEnoughArguments,
+    UnknownOption(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue { option: String, value: String },
+    PatternFile { path: String, message: String },
+    // `path` includes the line number when a particular line is at fault.
+    ConfigFile { path: String, message: String },
+    ConflictingOptions(String, String),
+    RequiresOption(String, String),
+    // Not failures, but they stop parsing the same way.
+    HelpRequested,
+    VersionRequested,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::NotEnoughArguments => write!(f, "not enough arguments"),
+            ConfigError::UnknownOption(option) => write!(f, "unknown option '{option}'"),
+            ConfigError::MissingValue(option) => write!(f, "option '{option}' needs a value"),
+            ConfigError::UnexpectedValue(option) => {
+                write!(f, "option '{option}' does not take a value")
+            }
+            ConfigError::InvalidValue { option, value } => {
+                write!(f, "invalid value '{value}' for option '{option}'")
+            }
+            ConfigError::PatternFile { path, message } => {
+                write!(f, "cannot read patterns from '{path}': {message}")
+            }
+            ConfigError::ConfigFile { path, message } => write!(f, "{path}: {message}"),
+            ConfigError::ConflictingOptions(first, second) => {
+                write!(f, "options '{first}' and '{second}' can't be used together")
+            }
+            ConfigError::RequiresOption(option, required) => {
+                write!(f, "option '{option}' requires '{required}'")
+            }
+            ConfigError::HelpRequested => write!(f, "help requested"),
+            ConfigError::VersionRequested => write!(f, "version requested"),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ColorChoice {
+    // Color only when writing to a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// Where a setting came from, for --debug-config.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+    Environment(&'static str),
+    ConfigFile { path: String, line: usize },
+    CommandLine,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::Environment(name) => write!(f, "environment variable {name}"),
+            Origin::ConfigFile { path, line } => write!(f, "{path}:{line}"),
+            Origin::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+// An option as it appeared in some source, with its value if it takes one.
+type Arg = (&'static Opt, Option<String>);
+
+struct Setting {
+    opt: &'static Opt,
+    value: Option<String>,
+    origin: Origin,
+}
+
+// The contents of the config file, read before parsing starts.
+pub struct ConfigFile {
+    pub path: String,
+    pub contents: String,
+}
+
+impl ConfigFile {
+    // Reads $MINIGREP_CONFIG_PATH, or ~/.minigreprc if the variable isn't
+    // set. An empty MINIGREP_CONFIG_PATH means no config file, and so does
+    // a missing ~/.minigreprc.
+    pub fn load() -> Result<Option<ConfigFile>, ConfigError> {
+        let (path, explicit) = match env::var_os("MINIGREP_CONFIG_PATH") {
+            Some(path) if path.is_empty() => return Ok(None),
+            Some(path) => (PathBuf::from(path), true),
+            None => match env::var_os("HOME") {
+                Some(home) => (PathBuf::from(home).join(".minigreprc"), false),
+                None => return Ok(None),
+            },
+        };
+        let path_name = path.display().to_string();
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Some(ConfigFile { path: path_name, contents })),
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !explicit => Ok(None),
+            Err(err) => Err(ConfigError::ConfigFile { path: path_name, message: err.to_string() }),
+        }
+    }
+
+    // The options on each line, with their line numbers. A line holds one
+    // option, optionally followed by whitespace and its value.
+    fn options(&self) -> Result<Vec<(usize, Arg)>, ConfigError> {
+        let mut options = Vec::new();
+        for (number, line) in self.contents.lines().enumerate() {
+            let number = number + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| ConfigError::ConfigFile {
+                path: format!("{}:{number}", self.path),
+                message,
+            };
+
+            let args: Vec<String> = match line.split_once(char::is_whitespace) {
+                Some((option, value)) if option.starts_with('-') => {
+                    vec![option.to_string(), value.trim_start().to_string()]
+                }
+                _ => vec![line.to_string()],
+            };
+            let (found, positional) = read_args(&args).map_err(|err| error(err.to_string()))?;
+            if !positional.is_empty() {
+                return Err(error("only options can be set in a config file".to_string()));
+            }
+            for (opt, value) in found {
+                if is_pattern_option(opt) || matches!(opt.long, "no-config" | "help" | "version") {
+                    return Err(error(format!("{} can't be set in a config file", opt_name(opt))));
+                }
+                options.push((number, (opt, value)));
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Default)]
+pub struct Config {
+    // A line matches if it matches any of these.
+    pub patterns: Vec<String>,
+    pub paths: Vec<String>,
+    pub ignore_case: bool,
+    pub turkic: bool,
+    pub normalization: Normalization,
+    pub regex: bool,
+    pub boundary: Boundary,
+    // Maximum number of edits for fuzzy matching, if it's on.
+    pub fuzzy: Option<usize>,
+    // A boolean query (--query), used instead of the patterns.
+    pub query: Option<String>,
+    pub invert: bool,
+    pub replace: Option<String>,
+    pub in_place: bool,
+    pub dry_run: bool,
+    pub read: ReadOptions,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // Names given to --type, and the globs --type-add gave to names.
+    pub types: Vec<String>,
+    pub type_globs: Vec<(String, String)>,
+    pub hidden: bool,
+    pub no_ignore: bool,
+    // Narrow down directory searches with their trigram index.
+    pub index: bool,
+    // Keep printing lines appended to the files (--follow).
+    pub follow: bool,
+    // Search changed files again until interrupted (--watch).
+    pub watch: bool,
+    // Browse results instead of printing them (--interactive).
+    pub interactive: bool,
+    // Report what was searched and where the time went (--stats).
+    pub stats: bool,
+    pub print: PrintOptions,
+    pub color: ColorChoice,
+    // 0 means one thread per CPU.
+    pub threads: usize,
+    pub debug_config: bool,
+    // Path of the config file that was read, if any.
+    pub config_file: Option<String>,
+    // The options in effect, in the order they were applied.
+    settings: Vec<Setting>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config, ConfigError> {
+        let ignore_case = env::var("IGNORE_CASE").is_ok();
+        Config::parse(args, ignore_case, ConfigFile::load)
+    }
+
+    // The config file is only loaded if the command line doesn't say
+    // --no-config, --help or --version.
+    fn parse(
+        args: &[String],
+        ignore_case: bool,
+        load_config_file: impl FnOnce() -> Result<Option<ConfigFile>, ConfigError>,
+    ) -> Result<Config, ConfigError> {
+        let (options, positional) = read_args(args.get(1..).unwrap_or_default())?;
+        let mut config = Config::default();
+
+        for (opt, _) in &options {
+            if matches!(opt.long, "help" | "version") {
+                config.apply(opt, None)?;
+            }
+        }
+        if ignore_case {
+            config.set(option("ignore-case"), None, Origin::Environment("IGNORE_CASE"))?;
+        }
+        if !options.iter().any(|(opt, _)| opt.long == "no-config") {
+            if let Some(file) = load_config_file()? {
+                for (line, (opt, value)) in file.options()? {
+                    let origin = Origin::ConfigFile { path: file.path.clone(), line };
+                    config.set(opt, value, origin)?;
+                }
+                config.config_file = Some(file.path);
+            }
+        }
+
+        // Whether -e, -f or --query was used, even if a pattern file was empty.
+        let patterns_given = options.iter().any(|(opt, _)| is_pattern_option(opt));
+        for (opt, value) in options {
+            config.set(opt, value, Origin::CommandLine)?;
+        }
+
+        let mut positional = positional.into_iter();
+        if !patterns_given {
+            let query = positional.next().ok_or(ConfigError::NotEnoughArguments)?;
+            config.patterns.push(query);
+        }
+        config.paths = positional.collect();
+        if config.paths.is_empty() {
+            // The interactive view needs standard input for keys.
+            let default = if config.interactive { "." } else { "-" };
+            config.paths.push(default.to_string());
+        }
+
+        for name in &config.types {
+            let globs = config.globs_for_type(name);
+            if globs.is_empty() {
+                let value = name.clone();
+                return Err(ConfigError::InvalidValue { option: "--type".to_string(), value });
+            }
+            config.include.extend(globs);
+        }
+
+        if config.regex && config.fuzzy.is_some() {
+            return Err(ConfigError::ConflictingOptions("--regex".to_string(), "--fuzzy".to_string()));
+        }
+        if config.query.is_some() {
+            // Terms are matched on their own, so none of these would apply.
+            let conflicts = [
+                (!config.patterns.is_empty(), "--regexp"),
+                (config.regex, "--regex"),
+                (config.fuzzy.is_some(), "--fuzzy"),
+                (config.boundary == Boundary::Line, "--line-regexp"),
+                (config.read.multiline, "--multiline"),
+            ];
+            if let Some((_, other)) = conflicts.iter().find(|(conflict, _)| *conflict) {
+                return Err(ConfigError::ConflictingOptions("--query".to_string(), other.to_string()));
+            }
+        }
+        let summary_option = match config.print.mode {
+            Mode::Lines => None,
+            Mode::Count => Some("--count"),
+            Mode::FilesWithMatches => Some("--files-with-matches"),
+            Mode::FilesWithoutMatch => Some("--files-without-match"),
+        };
+        if let (true, Some(option)) = (config.print.json, summary_option) {
+            return Err(ConfigError::ConflictingOptions("--json".to_string(), option.to_string()));
+        }
+        if config.follow && config.watch {
+            return Err(ConfigError::ConflictingOptions("--follow".to_string(), "--watch".to_string()));
+        }
+        // Both run until interrupted, so they can't print a final summary,
+        // statistics included, or rewrite files.
+        for (set, option) in [(config.follow, "--follow"), (config.watch, "--watch")] {
+            let conflicts = [
+                (summary_option.is_some(), summary_option.unwrap_or_default()),
+                (config.in_place, "--in-place"),
+                (config.dry_run, "--dry-run"),
+                (config.stats, "--stats"),
+                (config.follow && config.read.multiline, "--multiline"),
+                (config.follow && config.read.encoding.is_some(), "--encoding"),
+            ];
+            if let Some((_, other)) = conflicts.iter().find(|(conflict, _)| set && *conflict) {
+                return Err(ConfigError::ConflictingOptions(option.to_string(), other.to_string()));
+            }
+        }
+        if config.interactive {
+            let conflicts = [
+                (summary_option.is_some(), summary_option.unwrap_or_default()),
+                (config.print.json, "--json"),
+                (config.in_place, "--in-place"),
+                (config.dry_run, "--dry-run"),
+                (config.follow, "--follow"),
+                (config.watch, "--watch"),
+                (config.stats, "--stats"),
+            ];
+            if let Some((_, other)) = conflicts.iter().find(|(conflict, _)| *conflict) {
+                return Err(ConfigError::ConflictingOptions("--interactive".to_string(), other.to_string()));
+            }
+            if config.paths.iter().any(|path| path == "-") {
+                return Err(ConfigError::RequiresOption("--interactive".to_string(), "PATH".to_string()));
+            }
+        }
+        for (set, option) in [(config.in_place, "--in-place"), (config.dry_run, "--dry-run")] {
+            if set && config.replace.is_none() {
+                return Err(ConfigError::RequiresOption(option.to_string(), "--replace".to_string()));
+            }
+            if set && config.read.multiline {
+                return Err(ConfigError::ConflictingOptions(option.to_string(), "--multiline".to_string()));
+            }
+            if set && config.read.encoding.is_some_and(|encoding| encoding != Encoding::Utf8) {
+                return Err(ConfigError::ConflictingOptions(option.to_string(), "--encoding".to_string()));
+            }
+            if set && config.invert {
+                return Err(ConfigError::ConflictingOptions(
+                    option.to_string(),
+                    "--invert-match".to_string(),
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+
+    // Applies an option and remembers where it came from, forgetting any
+    // earlier setting it overrides.
+    fn set(
+        &mut self,
+        opt: &'static Opt,
+        value: Option<String>,
+        origin: Origin,
+    ) -> Result<(), ConfigError> {
+        self.apply(opt, value.clone())?;
+        if !is_repeatable(opt) {
+            self.settings.retain(|setting| setting_name(setting.opt) != setting_name(opt));
+        }
+        self.settings.push(Setting { opt, value, origin });
+        Ok(())
+    }
+
+    fn apply(&mut self, opt: &Opt, value: Option<String>) -> Result<(), ConfigError> {
+        match opt.long {
+            "regexp" => self.patterns.extend(value),
+            "file" => {
+                let path = value.unwrap_or_default();
+                let contents = read_patterns(&path).map_err(|err| ConfigError::PatternFile {
+                    path: path.clone(),
+                    message: err.to_string(),
+                })?;
+                self.patterns.extend(contents.lines().map(str::to_string));
+            }
+            "ignore-case" => self.ignore_case = true,
+            "no-ignore-case" => self.ignore_case = false,
+            "turkic" => self.turkic = true,
+            "normalize" => {
+                self.normalization = match value.as_deref() {
+                    Some("nfc") => Normalization::Nfc,
+                    Some("nfd") => Normalization::Nfd,
+                    _ => return Err(invalid(opt, value)),
+                }
+            }
+            "regex" => self.regex = true,
+            // Like grep, -x wins over -w whichever comes first.
+            "word-regexp" if self.boundary != Boundary::Line => self.boundary = Boundary::Word,
+            "word-regexp" => {}
+            "line-regexp" => self.boundary = Boundary::Line,
+            "fuzzy" => self.fuzzy = Some(number(opt, value)?),
+            "query" => self.query = value,
+            "multiline" => self.read.multiline = true,
+            "null-data" => {
+                self.read.null_data = true;
+                self.print.null_data = true;
+            }
+            "invert-match" => self.invert = true,
+            "replace" => self.replace = value,
+            "in-place" => self.in_place = true,
+            "dry-run" => self.dry_run = true,
+            "encoding" => {
+                let encoding = value.as_deref().and_then(Encoding::from_label);
+                self.read.encoding = Some(encoding.ok_or_else(|| invalid(opt, value))?);
+            }
+            "count" => self.print.mode = Mode::Count,
+            "files-with-matches" => self.print.mode = Mode::FilesWithMatches,
+            "files-without-match" => self.print.mode = Mode::FilesWithoutMatch,
+            "max-count" => self.print.max_count = Some(number(opt, value)?),
+            "line-number" => self.print.line_number = true,
+            "byte-offset" => self.print.byte_offset = true,
+            "after-context" => self.print.after_context = number(opt, value)?,
+            "before-context" => self.print.before_context = number(opt, value)?,
+            // -A and -B win over -C, whichever order they come in.
+            "context" => {
+                let lines = number(opt, value)?;
+                let is_set = |long| self.settings.iter().any(|setting| setting.opt.long == long);
+                let (before_set, after_set) = (is_set("before-context"), is_set("after-context"));
+                if !before_set {
+                    self.print.before_context = lines;
+                }
+                if !after_set {
+                    self.print.after_context = lines;
+                }
+            }
+            "color" => {
+                self.color = match value.as_deref() {
+                    Some("auto") => ColorChoice::Auto,
+                    Some("always") => ColorChoice::Always,
+                    Some("never") => ColorChoice::Never,
+                    _ => return Err(invalid(opt, value)),
+                }
+            }
+            "json" => self.print.json = true,
+            "threads" => self.threads = number(opt, value)?,
+            "include" => self.include.extend(value),
+            "exclude" => self.exclude.extend(value),
+            "type" => self.types.extend(value),
+            "type-add" => {
+                let definition = value.as_deref().unwrap_or_default();
+                let (name, globs) = match definition.split_once(':') {
+                    Some((name, globs)) if !name.is_empty() && !globs.is_empty() => (name, globs),
+                    _ => return Err(invalid(opt, value)),
+                };
+                for glob in globs.split(',') {
+                    self.type_globs.push((name.to_string(), glob.to_string()));
+                }
+            }
+            "hidden" => self.hidden = true,
+            "no-ignore" => self.no_ignore = true,
+            "index" => self.index = true,
+            "follow" => self.follow = true,
+            "watch" => self.watch = true,
+            "interactive" => self.interactive = true,
+            "stats" => self.stats = true,
+            "no-config" => {}
+            "debug-config" => self.debug_config = true,
+            "help" => return Err(ConfigError::HelpRequested),
+            "version" => return Err(ConfigError::VersionRequested),
+            _ => unreachable!("option --{} has no handler", opt.long),
+        }
+        Ok(())
+    }
+
+    pub fn help() -> String {
+        let mut help = format!("{}\n{}\n\nOptions:\n", Config::version(), USAGE);
+
+        for opt in OPTIONS {
+            let short = match opt.short {
+                Some(c) => format!("-{c}, "),
+                None => "    ".to_string(),
+            };
+            let value = opt.value.map(|value| format!(" {value}")).unwrap_or_default();
+            let flags = format!("{short}--{}{value}", opt.long);
+            help.push_str(&format!("  {flags:<28}{}\n", opt.help));
+        }
+
+        let types: Vec<&str> = TYPES.iter().map(|(name, _)| *name).collect();
+        help.push_str(&format!("\nTypes for --type: {}\n", types.join(", ")));
+        help
+    }
+
+    // What --debug-config prints: each setting in effect and its origin.
+    pub fn describe_settings(&self) -> String {
+        let file = self.config_file.as_deref().unwrap_or("none");
+        let mut description = format!("config file: {file}\n");
+        for setting in &self.settings {
+            let value = setting.value.as_ref().map(|value| format!("={value}")).unwrap_or_default();
+            let flag = format!("--{}{value}", setting.opt.long);
+            description.push_str(&format!("  {flag:<28}{}\n", setting.origin));
+        }
+        description
+    }
+
+    // Built-in globs for a type come first, then those from --type-add.
+    fn globs_for_type(&self, name: &str) -> Vec<String> {
+        let builtin = TYPES.iter().filter(|(builtin, _)| *builtin == name);
+        let builtin = builtin.flat_map(|(_, globs)| globs.iter().map(|glob| glob.to_string()));
+        let added = self.type_globs.iter().filter(|(added, _)| added == name);
+        builtin.chain(added.map(|(_, glob)| glob.clone())).collect()
+    }
+
+    pub fn version() -> String {
+        format!("minigrep {}", env!("CARGO_PKG_VERSION"))
+    }
+}
+
+// Splits arguments into options with their values and positional
+// arguments, without applying anything yet.
+fn read_args(args: &[String]) -> Result<(Vec<Arg>, Vec<String>), ConfigError> {
+    let mut options = Vec::new();
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref().cloned());
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let opt = OPTIONS
+                .iter()
+                .find(|opt| opt.long == name)
+                .ok_or_else(|| ConfigError::UnknownOption(format!("--{name}")))?;
+
+            let value = match (opt.value, inline) {
+                (Some(_), Some(value)) => Some(value),
+                (Some(_), None) => Some(next_value(opt, &mut args)?),
+                (None, Some(_)) => return Err(ConfigError::UnexpectedValue(opt_name(opt))),
+                (None, None) => None,
+            };
+            options.push((opt, value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            // A cluster of short flags such as -in or -nC2.
+            let cluster = &arg[1..];
+            for (i, c) in cluster.char_indices() {
+                let opt = OPTIONS
+                    .iter()
+                    .find(|opt| opt.short == Some(c))
+                    .ok_or_else(|| ConfigError::UnknownOption(format!("-{c}")))?;
+
+                if opt.value.is_none() {
+                    options.push((opt, None));
+                    continue;
+                }
+
+                // The rest of the cluster is the value, if there is any.
+                let rest = &cluster[i + c.len_utf8()..];
+                let value = if rest.is_empty() {
+                    next_value(opt, &mut args)?
+                } else {
+                    rest.to_string()
+                };
+                options.push((opt, Some(value)));
+                break;
+            }
+        } else {
+            positional.push(arg.clone());
+        }
+    }
+
+    Ok((options, positional))
+}
+
+fn option(long: &str) -> &'static Opt {
+    OPTIONS.iter().find(|opt| opt.long == long).expect("option is in the table")
+}
+
+fn is_pattern_option(opt: &Opt) -> bool {
+    matches!(opt.long, "regexp" | "file" | "query")
+}
+
+// Options that add to a list rather than replace an earlier value.
+fn is_repeatable(opt: &Opt) -> bool {
+    matches!(opt.long, "regexp" | "file" | "include" | "exclude" | "type" | "type-add")
+}
+
+// Options that set the same thing override each other.
+fn setting_name(opt: &Opt) -> &'static str {
+    match opt.long {
+        "no-ignore-case" => "ignore-case",
+        "files-with-matches" | "files-without-match" => "count",
+        long => long,
+    }
+}
+
+fn read_patterns(path: &str) -> io::Result<String> {
+    if path == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        Ok(contents)
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+fn opt_name(opt: &Opt) -> String {
+    format!("--{}", opt.long)
+}
+
+fn next_value<'a>(
+    opt: &Opt,
+    args: &mut impl Iterator<Item = &'a String>,
+) -> Result<String, ConfigError> {
+    args.next()
+        .cloned()
+        .ok_or_else(|| ConfigError::MissingValue(opt_name(opt)))
+}
+
+fn number(opt: &Opt, value: Option<String>) -> Result<usize, ConfigError> {
+    let parsed = value.as_deref().unwrap_or_default().parse();
+    parsed.map_err(|_| invalid(opt, value))
+}
+
+fn invalid(opt: &Opt, value: Option<String>) -> ConfigError {
+    ConfigError::InvalidValue { option: opt_name(opt), value: value.unwrap_or_default() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Config::parse(&args, false, || Ok(None))
+    }
+
+    #[test]
+    fn regex_flag() {
+        let config = parse(&["minigrep", "--regex", "a|b", "poem.txt"]).unwrap();
+
+        assert!(config.regex);
+        assert_eq!(vec!["a|b"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+    }
+
+    #[test]
+    fn glob_filters() {
+        let config =
+            parse(&["minigrep", "--include=*.rs", "fn", "src", "--exclude", "prior_*"]).unwrap();
+
+        assert_eq!(vec!["*.rs"], config.include);
+        assert_eq!(vec!["prior_*"], config.exclude);
+        assert_eq!(vec!["src"], config.paths);
+        assert!(!config.hidden && !config.no_ignore);
+
+        let config = parse(&["minigrep", "--hidden", "--no-ignore", "fn", "src"]).unwrap();
+        assert!(config.hidden && config.no_ignore);
+    }
+
+    #[test]
+    fn output_flags() {
+        let config = parse(&["minigrep", "-n", "-C", "2", "-A", "3", "to", "poem.txt"]).unwrap();
+
+        assert!(config.print.line_number);
+        assert!(!config.print.byte_offset);
+        assert_eq!(2, config.print.before_context);
+        assert_eq!(3, config.print.after_context);
+
+        let config = parse(&["minigrep", "-A", "5", "-C", "1", "to", "poem.txt"]).unwrap();
+        assert_eq!(1, config.print.before_context);
+        assert_eq!(5, config.print.after_context);
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let config = parse(&["minigrep", "-inB2", "to", "poem.txt"]).unwrap();
+
+        assert!(config.ignore_case);
+        assert!(config.print.line_number);
+        assert_eq!(2, config.print.before_context);
+    }
+
+    #[test]
+    fn color_choice() {
+        assert_eq!(ColorChoice::Auto, parse(&["minigrep", "to"]).unwrap().color);
+        assert_eq!(
+            ColorChoice::Never,
+            parse(&["minigrep", "--color=never", "to"]).unwrap().color
+        );
+        assert!(parse(&["minigrep", "--color", "sometimes", "to"]).is_err());
+    }
+
+    #[test]
+    fn replace_flags() {
+        let config = parse(&["minigrep", "-E", "--replace", "$1?", "--dry-run", "(x)", "src"]).unwrap();
+        assert_eq!(Some("$1?".to_string()), config.replace);
+        assert!(config.dry_run);
+        assert!(!config.in_place);
+
+        assert_eq!(
+            ConfigError::RequiresOption("--in-place".to_string(), "--replace".to_string()),
+            parse(&["minigrep", "--in-place", "x", "src"]).err().unwrap()
+        );
+        assert!(parse(&["minigrep", "-v", "--replace=y", "--in-place", "x"]).is_err());
+    }
+
+    #[test]
+    fn json_output() {
+        assert!(parse(&["minigrep", "--json", "to", "poem.txt"]).unwrap().print.json);
+        assert_eq!(
+            ConfigError::ConflictingOptions("--json".to_string(), "--count".to_string()),
+            parse(&["minigrep", "--json", "-c", "to"]).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn unicode_flags() {
+        let config = parse(&["minigrep", "-i", "--turkic", "--normalize=nfd", "to"]).unwrap();
+
+        assert!(config.turkic);
+        assert_eq!(Normalization::Nfd, config.normalization);
+        assert!(parse(&["minigrep", "--normalize", "nfkc", "to"]).is_err());
+
+        let config = parse(&["minigrep", "--encoding", "Latin-1", "to"]).unwrap();
+        assert_eq!(Some(Encoding::Latin1), config.read.encoding);
+        assert!(parse(&["minigrep", "--encoding=ebcdic", "to"]).is_err());
+    }
+
+    #[test]
+    fn matching_modes() {
+        let config = parse(&["minigrep", "-w", "--fuzzy=2", "recieve", "notes.txt"]).unwrap();
+        assert_eq!(Boundary::Word, config.boundary);
+        assert_eq!(Some(2), config.fuzzy);
+
+        assert_eq!(Boundary::Line, parse(&["minigrep", "-xw", "to"]).unwrap().boundary);
+        assert_eq!(
+            ConfigError::ConflictingOptions("--regex".to_string(), "--fuzzy".to_string()),
+            parse(&["minigrep", "-E", "--fuzzy", "1", "t.o"]).err().unwrap()
+        );
+
+        let config = parse(&["minigrep", "-Uz", "-E", "fn\\s+main"]).unwrap();
+        assert!(config.read.multiline && config.read.null_data && config.print.null_data);
+
+        // With --query, every positional argument is a path.
+        let config = parse(&["minigrep", "--query", "disk NOT retry", "app.log"]).unwrap();
+        assert_eq!(Some("disk NOT retry".to_string()), config.query);
+        assert!(config.patterns.is_empty());
+        assert_eq!(vec!["app.log"], config.paths);
+        assert_eq!(
+            ConfigError::ConflictingOptions("--query".to_string(), "--regex".to_string()),
+            parse(&["minigrep", "-E", "--query", "disk"]).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn summary_flags() {
+        let config = parse(&["minigrep", "-vcm", "5", "to", "poem.txt"]).unwrap();
+
+        assert!(config.invert);
+        assert_eq!(Mode::Count, config.print.mode);
+        assert_eq!(Some(5), config.print.max_count);
+
+        // The last of -c, -l and -L wins.
+        let config = parse(&["minigrep", "-c", "-L", "to", "poem.txt"]).unwrap();
+        assert_eq!(Mode::FilesWithoutMatch, config.print.mode);
+    }
+
+    #[test]
+    fn follow_and_watch() {
+        assert!(parse(&["minigrep", "--follow", "ERROR", "app.log"]).unwrap().follow);
+        assert!(parse(&["minigrep", "--watch", "-n", "TODO", "src"]).unwrap().watch);
+        assert_eq!(
+            ConfigError::ConflictingOptions("--follow".to_string(), "--watch".to_string()),
+            parse(&["minigrep", "--follow", "--watch", "ERROR", "app.log"]).err().unwrap()
+        );
+        assert_eq!(
+            ConfigError::ConflictingOptions("--watch".to_string(), "--count".to_string()),
+            parse(&["minigrep", "--watch", "-c", "TODO", "src"]).err().unwrap()
+        );
+        assert_eq!(
+            ConfigError::ConflictingOptions("--follow".to_string(), "--multiline".to_string()),
+            parse(&["minigrep", "-U", "--follow", "ERROR", "app.log"]).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn interactive() {
+        assert_eq!(vec!["."], parse(&["minigrep", "--interactive", "TODO"]).unwrap().paths);
+        assert_eq!(
+            ConfigError::ConflictingOptions("--interactive".to_string(), "--json".to_string()),
+            parse(&["minigrep", "--interactive", "--json", "TODO"]).err().unwrap()
+        );
+        assert_eq!(
+            ConfigError::RequiresOption("--interactive".to_string(), "PATH".to_string()),
+            parse(&["minigrep", "--interactive", "TODO", "-"]).err().unwrap()
+        );
+        assert!(parse(&["minigrep", "-i", "TODO"]).unwrap().ignore_case);
+        assert!(Config::help().contains("-i stays --ignore-case"));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let config = parse(&["minigrep", "-n", "--", "-n", "poem.txt"]).unwrap();
+
+        assert!(config.print.line_number);
+        assert_eq!(vec!["-n"], config.patterns);
+    }
+
+    #[test]
+    fn several_patterns() {
+        let config = parse(&["minigrep", "-e", "to", "--regexp=be", "poem.txt"]).unwrap();
+
+        assert_eq!(vec!["to", "be"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+    }
+
+    #[test]
+    fn pattern_files() {
+        let dir = TempDir::new("patterns");
+        let path = dir.write("patterns.txt", "password\ntoken\n");
+        let file = path.to_str().unwrap();
+
+        let config = parse(&["minigrep", "-eKEY", "-f", file, "audit.log"]).unwrap();
+        assert_eq!(vec!["KEY", "password", "token"], config.patterns);
+        assert_eq!(vec!["audit.log"], config.paths);
+
+        // An empty pattern file still counts as giving the patterns.
+        fs::write(&path, "").unwrap();
+        let config = parse(&["minigrep", "-f", file, "audit.log"]).unwrap();
+        assert!(config.patterns.is_empty());
+        assert_eq!(vec!["audit.log"], config.paths);
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            parse(&["minigrep", "-f", file]),
+            Err(ConfigError::PatternFile { .. })
+        ));
+    }
+
+    #[test]
+    fn paths_default_to_stdin() {
+        assert_eq!(vec!["-"], parse(&["minigrep", "to"]).unwrap().paths);
+    }
+
+    #[test]
+    fn several_paths() {
+        let config = parse(&["minigrep", "-j", "4", "to", "poem.txt", "src", "-"]).unwrap();
+
+        assert_eq!(vec!["poem.txt", "src", "-"], config.paths);
+        assert_eq!(4, config.threads);
+    }
+
+    #[test]
+    fn flags_override_environment() {
+        let args: Vec<String> = ["minigrep", "--no-ignore-case", "to", "poem.txt"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        assert!(!Config::parse(&args, true, || Ok(None)).unwrap().ignore_case);
+        assert!(Config::parse(&args[..1], true, || Ok(None)).is_err());
+    }
+
+    #[test]
+    fn config_file_and_types() {
+        let rc = || {
+            let contents = "\
+# defaults
+--line-number
+--context 2
+-i
+--type-add=web:*.html,*.css
+";
+            Ok(Some(ConfigFile { path: "rc".to_string(), contents: contents.to_string() }))
+        };
+        let args = ["minigrep", "-C1", "--no-ignore-case", "-tweb", "--type", "rust", "to", "."]
+            .map(String::from);
+
+        let config = Config::parse(&args, true, rc).unwrap();
+        assert!(config.print.line_number);
+        assert!(!config.ignore_case);
+        assert_eq!(1, config.print.before_context);
+        assert_eq!(vec!["*.html", "*.css", "*.rs"], config.include);
+        assert_eq!(
+            "\
+config file: rc
+  --line-number               rc:2
+  --type-add=web:*.html,*.css rc:5
+  --context=1                 command line
+  --no-ignore-case            command line
+  --type=web                  command line
+  --type=rust                 command line
+",
+            config.describe_settings()
+        );
+
+        let args_no_config = ["minigrep", "--no-config", "to"].map(String::from);
+        assert!(!Config::parse(&args_no_config, false, rc).unwrap().print.line_number);
+
+        let bad = |contents: &str| {
+            let file = ConfigFile { path: "rc".to_string(), contents: contents.to_string() };
+            Config::parse(&args[..1], false, || Ok(Some(file))).err().unwrap().to_string()
+        };
+        assert_eq!("rc:2: only options can be set in a config file", bad("-n\npoem.txt"));
+        assert_eq!("rc:1: unknown option '--nope'", bad("--nope"));
+        assert_eq!("rc:1: --regexp can't be set in a config file", bad("-e to"));
+        assert_eq!(
+            ConfigError::InvalidValue { option: "--type".to_string(), value: "cobol".to_string() },
+            parse(&["minigrep", "-t", "cobol", "to"]).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(ConfigError::NotEnoughArguments, parse(&["minigrep", "-n"]).err().unwrap());
+        assert_eq!(
+            ConfigError::UnknownOption("-y".to_string()),
+            parse(&["minigrep", "-y", "to", "poem.txt"]).err().unwrap()
+        );
+        assert_eq!(
+            ConfigError::MissingValue("--context".to_string()),
+            parse(&["minigrep", "to", "poem.txt", "-C"]).err().unwrap()
+        );
+        assert_eq!(
+            ConfigError::InvalidValue { option: "--after-context".to_string(), value: "x".to_string() },
+            parse(&["minigrep", "--after-context=x", "to", "poem.txt"]).err().unwrap()
+        );
+        assert_eq!(
+            ConfigError::UnexpectedValue("--regex".to_string()),
+            parse(&["minigrep", "--regex=yes", "to", "poem.txt"]).err().unwrap()
+        );
+        assert_eq!(
+            ConfigError::HelpRequested,
+            parse(&["minigrep", "--help"]).err().unwrap()
+        );
+    }
+}
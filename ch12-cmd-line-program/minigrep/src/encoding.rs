@@ -0,0 +1,217 @@
+// Decoding text that isn't UTF-8.
+//
+// A byte order mark at the start of a file decides its encoding; without
+// one, files are read as UTF-8 unless --encoding says otherwise. Decoded
+// text is handed to the searcher as UTF-8, so byte offsets and line
+// lengths refer to the decoded text rather than to the file.
+
+use std::io::{self, Read};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    // ISO-8859-1: every byte is the code point of the same value.
+    Latin1,
+    // Latin-1 with printable characters in place of most C1 controls.
+    Windows1252,
+}
+
+// What bytes 0x80..0xA0 stand for in Windows-1252. The five bytes it
+// leaves undefined map to the C1 controls, as in the WHATWG encoding spec.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+impl Encoding {
+    // Parses an --encoding value, ignoring case and punctuation.
+    pub fn from_label(label: &str) -> Option<Encoding> {
+        let label: String = label
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        match label.as_str() {
+            "utf8" => Some(Encoding::Utf8),
+            "utf16le" => Some(Encoding::Utf16Le),
+            "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "iso88591" => Some(Encoding::Latin1),
+            "windows1252" | "cp1252" => Some(Encoding::Windows1252),
+            _ => None,
+        }
+    }
+
+    // The encoding announced by a byte order mark at the start of
+    // `start`, together with the length of the mark.
+    pub fn sniff(start: &[u8]) -> Option<(Encoding, usize)> {
+        if start.starts_with(b"\xef\xbb\xbf") {
+            Some((Encoding::Utf8, 3))
+        } else if start.starts_with(b"\xff\xfe") {
+            Some((Encoding::Utf16Le, 2))
+        } else if start.starts_with(b"\xfe\xff") {
+            Some((Encoding::Utf16Be, 2))
+        } else {
+            None
+        }
+    }
+
+    pub fn is_utf16(self) -> bool {
+        matches!(self, Encoding::Utf16Le | Encoding::Utf16Be)
+    }
+
+    // Decodes as much of `bytes` as forms whole characters, appending it to
+    // `out`, and returns how many bytes were used. Invalid sequences become
+    // U+FFFD.
+    fn decode_partial(self, bytes: &[u8], out: &mut String) -> usize {
+        match self {
+            Encoding::Utf8 => {
+                let mut rest = bytes;
+                loop {
+                    let err = match std::str::from_utf8(rest) {
+                        Ok(text) => {
+                            out.push_str(text);
+                            return bytes.len();
+                        }
+                        Err(err) => err,
+                    };
+                    let (valid, after) = rest.split_at(err.valid_up_to());
+                    out.push_str(std::str::from_utf8(valid).expect("checked above"));
+                    match err.error_len() {
+                        Some(len) => {
+                            out.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        // The input ends partway through a character.
+                        None => return bytes.len() - after.len(),
+                    }
+                }
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = bytes.chunks_exact(2).map(|pair| match self {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                });
+                let mut units: Vec<u16> = units.collect();
+                // Leave a trailing high surrogate for the next call.
+                if units.last().is_some_and(|unit| (0xD800..0xDC00).contains(unit)) {
+                    units.pop();
+                }
+                let chars = char::decode_utf16(units.iter().copied());
+                out.extend(chars.map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)));
+                units.len() * 2
+            }
+            Encoding::Latin1 => {
+                out.extend(bytes.iter().map(|&b| char::from(b)));
+                bytes.len()
+            }
+            Encoding::Windows1252 => {
+                out.extend(bytes.iter().map(|&b| match b {
+                    0x80..=0x9F => WINDOWS_1252[usize::from(b - 0x80)],
+                    _ => char::from(b),
+                }));
+                bytes.len()
+            }
+        }
+    }
+}
+
+// Reads text in some encoding from `inner` and produces it as UTF-8.
+pub struct Decoder<R> {
+    inner: R,
+    encoding: Encoding,
+    // Input that doesn't form a whole character yet.
+    pending: Vec<u8>,
+    // Decoded text not yet read, starting at `pos`.
+    decoded: String,
+    pos: usize,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R, encoding: Encoding) -> Decoder<R> {
+        Decoder { inner, encoding, pending: Vec::new(), decoded: String::new(), pos: 0 }
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            self.decoded.clear();
+            self.pos = 0;
+
+            let mut chunk = [0; 8 * 1024];
+            let read = self.inner.read(&mut chunk)?;
+            if read == 0 {
+                if self.pending.is_empty() {
+                    return Ok(0);
+                }
+                // The input ended partway through a character.
+                self.pending.clear();
+                self.decoded.push(char::REPLACEMENT_CHARACTER);
+                break;
+            }
+
+            self.pending.extend_from_slice(&chunk[..read]);
+            let used = self.encoding.decode_partial(&self.pending, &mut self.decoded);
+            self.pending.drain(..used);
+        }
+
+        let rest = &self.decoded.as_bytes()[self.pos..];
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8], encoding: Encoding) -> String {
+        let mut text = String::new();
+        Decoder::new(bytes, encoding).read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn decodes_each_encoding() {
+        assert_eq!("é€", decode(b"\xe9\x80", Encoding::Windows1252));
+        assert_eq!("é\u{80}", decode(b"\xe9\x80", Encoding::Latin1));
+        assert_eq!("a\u{1F600}", decode(b"a\0\x3d\xd8\x00\xde", Encoding::Utf16Le));
+        assert_eq!("a\u{FFFD}", decode(b"\0a\xd8\x3d", Encoding::Utf16Be));
+        assert_eq!("a\u{FFFD}b", decode(b"a\xffb", Encoding::Utf8));
+
+        assert_eq!(Some(Encoding::Windows1252), Encoding::from_label("CP-1252"));
+        assert_eq!(Some((Encoding::Utf16Le, 2)), Encoding::sniff(b"\xff\xfea\0"));
+        assert_eq!(None, Encoding::sniff(b"plain"));
+    }
+
+    #[test]
+    fn decoder_handles_split_characters() {
+        // A reader that hands out one byte at a time splits every character.
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let len = self.0.len().min(buf.len()).min(1);
+                buf[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        let utf16: Vec<u8> =
+            "one\r\n\u{1F600}two\n".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let mut text = String::new();
+        Decoder::new(Trickle(&utf16), Encoding::Utf16Be).read_to_string(&mut text).unwrap();
+        assert_eq!("one\r\n\u{1F600}two\n", text);
+
+        let mut text = String::new();
+        let utf8 = Trickle("né\u{1F600}".as_bytes());
+        Decoder::new(utf8, Encoding::Utf8).read_to_string(&mut text).unwrap();
+        assert_eq!("né\u{1F600}", text);
+    }
+}
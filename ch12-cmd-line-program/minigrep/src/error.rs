@@ -0,0 +1,75 @@
+// The one error type of the library, so callers can tell a bad option from
+// a bad pattern or a file that couldn't be read.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::config::ConfigError;
+use crate::query::QueryError;
+use crate::regex::RegexError;
+
+#[derive(Debug)]
+pub enum MinigrepError {
+    Config(ConfigError),
+    Regex(RegexError),
+    Query(QueryError),
+    Io(io::Error),
+    // Some paths couldn't be searched or rewritten. Each one was reported
+    // on stderr when it came up, and the rest were still handled.
+    Unsearchable(usize),
+    Unrewritable(usize),
+    NotADirectory(String),
+}
+
+impl fmt::Display for MinigrepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MinigrepError::Config(err) => write!(f, "{err}"),
+            MinigrepError::Regex(err) => write!(f, "{err}"),
+            MinigrepError::Query(err) => write!(f, "{err}"),
+            MinigrepError::Io(err) => write!(f, "{err}"),
+            MinigrepError::Unsearchable(count) => write!(f, "{count} path(s) could not be searched"),
+            MinigrepError::Unrewritable(count) => {
+                write!(f, "{count} path(s) could not be rewritten")
+            }
+            MinigrepError::NotADirectory(path) => write!(f, "{path}: not a directory"),
+        }
+    }
+}
+
+impl Error for MinigrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MinigrepError::Config(err) => Some(err),
+            MinigrepError::Regex(err) => Some(err),
+            MinigrepError::Query(err) => Some(err),
+            MinigrepError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for MinigrepError {
+    fn from(err: ConfigError) -> MinigrepError {
+        MinigrepError::Config(err)
+    }
+}
+
+impl From<RegexError> for MinigrepError {
+    fn from(err: RegexError) -> MinigrepError {
+        MinigrepError::Regex(err)
+    }
+}
+
+impl From<QueryError> for MinigrepError {
+    fn from(err: QueryError) -> MinigrepError {
+        MinigrepError::Query(err)
+    }
+}
+
+impl From<io::Error> for MinigrepError {
+    fn from(err: io::Error) -> MinigrepError {
+        MinigrepError::Io(err)
+    }
+}
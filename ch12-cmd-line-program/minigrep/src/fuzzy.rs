@@ -0,0 +1,148 @@
+// Approximate matching: finds substrings within a given Levenshtein
+// distance of the query, for typo'd identifiers and misspelled words.
+//
+// The search runs in two passes. A forward pass (Sellers' algorithm) keeps
+// the edit distance between the query and the best substring ending at each
+// position, and picks the end where that distance is lowest. A backward pass
+// from that end then finds where the closest substring starts.
+
+use std::ops::Range;
+
+// Finds a substring of `text`, at or after index `from`, that is at most
+// `max_edits` insertions, deletions or substitutions away from `query`, and
+// that `accept` agrees to. Returns its range in `text` and its distance.
+//
+// Close matches come in runs of neighbouring ends. The first run with an
+// acceptable match wins, and within it the closest match, then the leftmost,
+// then the longest.
+pub fn find(
+    query: &[char],
+    text: &[char],
+    from: usize,
+    max_edits: usize,
+    accept: impl Fn(Range<usize>) -> bool,
+) -> Option<(Range<usize>, usize)> {
+    let m = query.len();
+    // column[j] is the distance between query[..j] and the best substring
+    // ending at the current position.
+    let mut column: Vec<usize> = (0..=m).collect();
+    let mut next = vec![0; m + 1];
+    let mut run = Vec::new();
+    if column[m] <= max_edits {
+        run.push(from);
+    }
+
+    for (end, &c) in text.iter().enumerate().skip(from) {
+        for j in 1..=m {
+            let substitute = column[j - 1] + usize::from(query[j - 1] != c);
+            next[j] = substitute.min(column[j] + 1).min(next[j - 1] + 1);
+        }
+        std::mem::swap(&mut column, &mut next);
+
+        if column[m] <= max_edits {
+            run.push(end + 1);
+            continue;
+        }
+        if let Some(found) = best_in_run(query, text, from, &run, max_edits, &accept) {
+            return Some(found);
+        }
+        run.clear();
+    }
+
+    best_in_run(query, text, from, &run, max_edits, &accept)
+}
+
+fn best_in_run(
+    query: &[char],
+    text: &[char],
+    from: usize,
+    ends: &[usize],
+    max_edits: usize,
+    accept: &impl Fn(Range<usize>) -> bool,
+) -> Option<(Range<usize>, usize)> {
+    let mut best: Option<(Range<usize>, usize)> = None;
+
+    for &end in ends {
+        for (start, distance) in starts(query, text, from, end, max_edits) {
+            let better = best.as_ref().is_none_or(|(best, best_distance)| {
+                (distance, start, usize::MAX - end)
+                    < (*best_distance, best.start, usize::MAX - best.end)
+            });
+            if better && accept(start..end) {
+                best = Some((start..end, distance));
+            }
+        }
+    }
+
+    best
+}
+
+// Every start at or after `from` for which text[start..end] is at most
+// `max_edits` away from `query`, with its distance.
+fn starts(
+    query: &[char],
+    text: &[char],
+    from: usize,
+    end: usize,
+    max_edits: usize,
+) -> Vec<(usize, usize)> {
+    let m = query.len();
+    // column[j] is the distance between the last j chars of the query and
+    // text[start..end].
+    let mut column: Vec<usize> = (0..=m).collect();
+    let mut next = vec![0; m + 1];
+    let mut starts = Vec::new();
+    if column[m] <= max_edits {
+        starts.push((end, column[m]));
+    }
+
+    for (i, &c) in text[from..end].iter().enumerate().rev() {
+        next[0] = column[0] + 1;
+        for j in 1..=m {
+            let substitute = column[j - 1] + usize::from(query[m - j] != c);
+            next[j] = substitute.min(column[j] + 1).min(next[j - 1] + 1);
+        }
+        std::mem::swap(&mut column, &mut next);
+
+        if column[m] <= max_edits {
+            starts.push((from + i, column[m]));
+        }
+        // Moving the start further left can only add edits from here on.
+        if column.iter().all(|&d| d > max_edits) {
+            break;
+        }
+    }
+
+    starts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_str(query: &str, text: &str, max_edits: usize) -> Option<(Range<usize>, usize)> {
+        let query: Vec<char> = query.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        find(&query, &text, 0, max_edits, |_| true)
+    }
+
+    #[test]
+    fn finds_closest_substring() {
+        assert_eq!(Some((6..11, 0)), find_str("hello", "well, hello there", 1));
+        assert_eq!(Some((3..10, 2)), find_str("recieve", "to receive", 2));
+        assert_eq!(Some((0..5, 1)), find_str("hello", "yellow jello", 1));
+        assert_eq!(Some((0..5, 1)), find_str("helo", "hello", 1));
+        assert_eq!(Some((1..6, 0)), find_str("hello", "xhello", 1));
+        assert_eq!(None, find_str("hello", "yellow jello", 0));
+    }
+
+    #[test]
+    fn rejected_matches_fall_back_to_others() {
+        let query: Vec<char> = "lenght".chars().collect();
+        let text: Vec<char> = "the length".chars().collect();
+
+        // "lengt" is one edit away, but "length" is the whole word.
+        assert_eq!(Some((4..9, 1)), find(&query, &text, 0, 2, |_| true));
+        assert_eq!(Some((4..10, 2)), find(&query, &text, 0, 2, |range| range.end == 10));
+    }
+}
@@ -0,0 +1,237 @@
+// Shell-style glob patterns for filtering file paths.
+//
+//   *      any run of characters except '/'
+//   ?      any single character except '/'
+//   [a-z]  a character class, [!a-z] or [^a-z] negates it
+//   **     any run of characters including '/'
+//   **/    zero or more whole directories
+//
+// A pattern without a '/' is matched against the file name only, so
+// "*.rs" matches "src/main.rs". A pattern with a '/' is matched against
+// the whole path relative to the directory being searched.
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    tokens: Vec<Token>,
+    match_path: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    Any,
+    Star,
+    DoubleStar,
+    Directories,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Glob {
+        let match_path = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let token = match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    i += 1;
+                    if chars.get(i + 1) == Some(&'/') {
+                        i += 1;
+                        Token::Directories
+                    } else {
+                        Token::DoubleStar
+                    }
+                }
+                '*' => Token::Star,
+                '?' => Token::Any,
+                '[' => match parse_class(&chars[i + 1..]) {
+                    Some((token, used)) => {
+                        i += used;
+                        token
+                    }
+                    // An unclosed '[' is just a literal.
+                    None => Token::Literal('['),
+                },
+                '\\' if i + 1 < chars.len() => {
+                    i += 1;
+                    Token::Literal(chars[i])
+                }
+                c => Token::Literal(c),
+            };
+            tokens.push(token);
+            i += 1;
+        }
+
+        Glob { tokens, match_path }
+    }
+
+    // `path` is relative to the search root and uses '/' as the separator.
+    pub fn is_match(&self, path: &str) -> bool {
+        let text = if self.match_path {
+            path
+        } else {
+            path.rsplit('/').next().unwrap_or(path)
+        };
+        let text: Vec<char> = text.chars().collect();
+        match_tokens(&self.tokens, &text)
+    }
+}
+
+// Parses the inside of a character class, returning the token and the
+// number of characters consumed including the closing ']'.
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let first = i;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == ']' && i > first {
+            return Some((Token::Class { ranges, negated }, i + 1));
+        }
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&hi| hi != ']') {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+
+    None
+}
+
+// Matches left to right, remembering where each star stopped. When the
+// text stops matching, the last star takes one more character and matching
+// resumes after it; once it can't take any more, the star before it does.
+// Trying every way of splitting the text between the stars is exponential,
+// but a star that can take whatever an earlier one would have makes that
+// one needless, so it is dropped: `*` drops any `*` just before it, and
+// `**` or `**/` drop all the stars before them unless a `**/` not after a
+// '/' is involved. This leaves one or two to try in all but odd
+// patterns.
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let (mut t, mut i) = (0, 0);
+    // For each star still to try: its token and where in the text it stopped.
+    let mut stars: Vec<(usize, usize)> = Vec::new();
+
+    loop {
+        let matched = match tokens.get(t) {
+            None => {
+                if i == text.len() {
+                    return true;
+                }
+                false
+            }
+            Some(Token::Literal(c)) => text.get(i) == Some(c),
+            Some(Token::Any) => text.get(i).is_some_and(|&c| c != '/'),
+            Some(Token::Class { ranges, negated }) => match text.get(i) {
+                Some(&c) if c != '/' => {
+                    let found = ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+                    found != *negated
+                }
+                _ => false,
+            },
+            Some(token) => {
+                if *token == Token::Star {
+                    while stars.last().is_some_and(|&(star, _)| tokens[star] == Token::Star) {
+                        stars.pop();
+                    }
+                } else if is_whole(tokens, t) && stars.iter().all(|&(s, _)| is_whole(tokens, s)) {
+                    stars.clear();
+                }
+                stars.push((t, i));
+                t += 1;
+                continue;
+            }
+        };
+        if matched {
+            t += 1;
+            i += 1;
+            continue;
+        }
+
+        loop {
+            let Some((star, start)) = stars.last_mut() else {
+                return false;
+            };
+            let resume = match tokens[*star] {
+                Token::Star => text.get(*start).is_some_and(|&c| c != '/').then_some(*start + 1),
+                // `**/` only ever stops just after a '/'.
+                Token::Directories => {
+                    text[*start..].iter().position(|&c| c == '/').map(|slash| *start + slash + 1)
+                }
+                _ => (*start < text.len()).then_some(*start + 1),
+            };
+            match resume {
+                Some(resume) => {
+                    *start = resume;
+                    (t, i) = (*star + 1, resume);
+                    break;
+                }
+                None => {
+                    stars.pop();
+                }
+            }
+        }
+    }
+}
+
+// Whether the `**` or `**/` at `t` can take whatever text the stars before
+// it might have left for it, which a `**/` in the middle of a name can't.
+fn is_whole(tokens: &[Token], t: usize) -> bool {
+    match tokens[t] {
+        Token::DoubleStar => true,
+        Token::Directories => t == 0 || tokens[t - 1] == Token::Literal('/'),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_name_patterns() {
+        let glob = Glob::new("*.rs");
+        assert!(glob.is_match("main.rs"));
+        assert!(glob.is_match("src/lib.rs"));
+        assert!(!glob.is_match("src/lib.rs.01"));
+
+        assert!(Glob::new("lib.rs.0[1-2]").is_match("prior_versions/lib.rs.02"));
+        assert!(!Glob::new("lib.rs.0[!1-2]").is_match("prior_versions/lib.rs.02"));
+        assert!(Glob::new("?oem.txt").is_match("data/poem.txt"));
+    }
+
+    #[test]
+    fn path_patterns() {
+        assert!(Glob::new("src/*.rs").is_match("src/lib.rs"));
+        assert!(!Glob::new("src/*.rs").is_match("src/prior/lib.rs"));
+        assert!(Glob::new("src/**/*.rs").is_match("src/lib.rs"));
+        assert!(Glob::new("src/**/*.rs").is_match("src/a/b/lib.rs"));
+        assert!(Glob::new("/data/**").is_match("data/poem.txt"));
+        assert!(Glob::new("**/test/*.rs").is_match("a/test/b/test/lib.rs"));
+        assert!(!Glob::new("**/test/*.rs").is_match("a/test/b/lib.rs"));
+        assert!(Glob::new("src/**/b*.rs").is_match("src/b/x/bar.rs"));
+        assert!(!Glob::new("a*/b").is_match("ax/y/b"));
+    }
+
+    #[test]
+    fn many_stars_match_quickly() {
+        // Trying every way to split the text between the stars would take
+        // far too long here.
+        let text = "a".repeat(100);
+        assert!(!Glob::new(&format!("{}b", "*a".repeat(20))).is_match(&text));
+        assert!(Glob::new(&"*a".repeat(20)).is_match(&text));
+        let path = format!("{}/{text}", "d/".repeat(50));
+        assert!(!Glob::new(&format!("**/{}b", "*a".repeat(20))).is_match(&path));
+        assert!(!Glob::new(&format!("{}b", "**a".repeat(10))).is_match(&path));
+    }
+}
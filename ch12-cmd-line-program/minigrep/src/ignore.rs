@@ -0,0 +1,219 @@
+// Ignore files: .gitignore, .ignore and git's global excludes.
+//
+// Each file holds one rule per line, using the .gitignore syntax:
+//
+//   # comment      ignored, as are blank lines
+//   *.log          matches a name at any depth
+//   /build         a leading or inner '/' anchors the rule to the directory
+//                  holding the ignore file
+//   target/        a trailing '/' only matches directories
+//   !keep.log      re-includes something an earlier rule ignored
+//
+// Within a file the last matching rule wins, and files in deeper
+// directories override the ones above them. A .ignore file overrides the
+// .gitignore in the same directory.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::glob::Glob;
+
+// Names of the per-directory ignore files, from lowest to highest priority.
+pub const FILE_NAMES: [&str; 2] = [".gitignore", ".ignore"];
+
+#[derive(Debug, Clone)]
+pub struct IgnoreFile {
+    // Anchored rules are relative to this directory.
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreFile {
+    pub fn parse(base: &Path, contents: &str) -> IgnoreFile {
+        let rules = contents.lines().filter_map(parse_rule).collect();
+        IgnoreFile { base: base.to_path_buf(), rules }
+    }
+
+    // Reads `path`, treating a missing or unreadable file as empty.
+    pub fn read(base: &Path, path: &Path) -> Option<IgnoreFile> {
+        let contents = fs::read_to_string(path).ok()?;
+        let file = IgnoreFile::parse(base, &contents);
+        (!file.rules.is_empty()).then_some(file)
+    }
+
+    // Some(true) if the last rule matching `path` ignores it, Some(false)
+    // if it re-includes it, and None if no rule matches.
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.base).ok()?;
+        let parts: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+        let relative = parts.join("/");
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.glob.is_match(&relative))
+            .map(|rule| !rule.negated)
+    }
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    // Trailing spaces don't count unless they're escaped.
+    let mut line = line.trim_end_matches('\r');
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line = &line[..line.len() - 1];
+    }
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    if line.is_empty() {
+        return None;
+    }
+
+    Some(Rule { glob: Glob::new(line), negated, dir_only })
+}
+
+// Decides whether a path is ignored, checking the most specific ignore
+// file first.
+pub fn is_ignored(files: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    files.iter().rev().find_map(|file| file.matched(path, is_dir)).unwrap_or(false)
+}
+
+// The ignore files that apply to `dir` from outside of it: those in its
+// parent directories up to the root of the git repository it is in, plus
+// the repository's info/exclude and git's global excludes file. `dir` must
+// be absolute. Returned from lowest to highest priority.
+pub fn inherited(dir: &Path) -> Vec<IgnoreFile> {
+    let ancestors: Vec<&Path> = dir.ancestors().skip(1).collect();
+    let repo = dir.ancestors().find(|dir| dir.join(".git").exists());
+    let mut files = Vec::new();
+
+    if let Some(repo) = repo {
+        if let Some(path) = global_excludes_path() {
+            files.extend(IgnoreFile::read(repo, &path));
+        }
+        files.extend(IgnoreFile::read(repo, &repo.join(".git/info/exclude")));
+
+        // Outermost first, stopping at the repository root.
+        let inside: Vec<&Path> =
+            ancestors.into_iter().take_while(|dir| dir.starts_with(repo)).collect();
+        for parent in inside.into_iter().rev() {
+            files.extend(in_dir(parent));
+        }
+    }
+
+    files
+}
+
+// The ignore files in `dir` itself.
+pub fn in_dir(dir: &Path) -> Vec<IgnoreFile> {
+    FILE_NAMES.iter().filter_map(|name| IgnoreFile::read(dir, &dir.join(name))).collect()
+}
+
+// Where git keeps its global excludes: core.excludesFile from the user's
+// git config, or else $XDG_CONFIG_HOME/git/ignore.
+fn global_excludes_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+
+    let config_files = [
+        home.as_ref().map(|home| home.join(".gitconfig")),
+        config_home.as_ref().map(|dir| dir.join("git/config")),
+    ];
+    let configured = config_files
+        .into_iter()
+        .flatten()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .find_map(|contents| excludes_file_setting(&contents));
+
+    match configured {
+        Some(path) => match (path.strip_prefix("~/"), &home) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(path)),
+        },
+        None => config_home.map(|dir| dir.join("git/ignore")),
+    }
+}
+
+// Finds core.excludesFile in the text of a git config file.
+fn excludes_file_setting(contents: &str) -> Option<String> {
+    let mut in_core = false;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_core = line.eq_ignore_ascii_case("[core]");
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if in_core && key.trim().eq_ignore_ascii_case("excludesfile") {
+            return Some(value.trim().trim_matches('"').to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(files: &[IgnoreFile], path: &str, is_dir: bool) -> bool {
+        is_ignored(files, &Path::new("/repo").join(path), is_dir)
+    }
+
+    #[test]
+    fn gitignore_rules() {
+        let root = IgnoreFile::parse(
+            Path::new("/repo"),
+            "# build output\ntarget/\n*.log\n!keep.log\n/build\ndocs/*.html\n\\#notes  \n",
+        );
+        let files = [root];
+
+        assert!(ignored(&files, "target", true));
+        assert!(ignored(&files, "crates/a/target", true));
+        assert!(!ignored(&files, "target", false));
+        assert!(ignored(&files, "src/debug.log", false));
+        assert!(!ignored(&files, "src/keep.log", false));
+        assert!(ignored(&files, "build", true));
+        assert!(!ignored(&files, "src/build", true));
+        assert!(ignored(&files, "docs/index.html", false));
+        assert!(!ignored(&files, "docs/api/index.html", false));
+        assert!(ignored(&files, "#notes", false));
+        assert!(!ignored(&files, "src/main.rs", false));
+    }
+
+    #[test]
+    fn deeper_files_override() {
+        let root = IgnoreFile::parse(Path::new("/repo"), "*.gen.rs\n");
+        let nested = IgnoreFile::parse(Path::new("/repo/src"), "!*.gen.rs\n");
+        let files = [root, nested];
+
+        assert!(!ignored(&files, "src/types.gen.rs", false));
+        assert!(ignored(&files, "tests/types.gen.rs", false));
+    }
+
+    #[test]
+    fn git_config_excludes_file() {
+        let config = "[user]\n\tname = A\n[core]\n\texcludesFile = \"~/.gitignore_global\"\n";
+        assert_eq!(Some("~/.gitignore_global".to_string()), excludes_file_setting(config));
+        assert_eq!(None, excludes_file_setting("[user]\nexcludesfile = x\n"));
+    }
+}
@@ -0,0 +1,420 @@
+// A trigram index of a directory, so repeated searches of a large tree
+// only have to read the files that can possibly match.
+//
+// `minigrep index build DIR` records, for every file under DIR, which
+// three-byte sequences it contains. A search with --index looks up the
+// trigrams of its patterns and skips files that lack any of them. Files
+// that changed since the index was built are searched regardless, so a
+// stale index makes searches slower but never wrong.
+//
+// The index lives in DIR/.minigrep-index. All numbers are little-endian:
+//
+//   magic    8 bytes   "MGINDEX\0"
+//   version  u32       FORMAT_VERSION
+//   files    u32 count, then per file:
+//              path      u32 length + UTF-8, relative to DIR with '/'
+//              modified  u64 seconds + u32 nanoseconds since the epoch
+//              size      u64
+//              indexed   u8, 0 if the contents couldn't be indexed
+//   trigrams u32 count, then per trigram:
+//              trigram   3 bytes
+//              files     u32 count + u32 file numbers, ascending
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::encoding::Encoding;
+use crate::output::Mode;
+use crate::unicode::{Folding, Normalization};
+use crate::{archive, replace, walk, Config};
+
+pub const FILE_NAME: &str = ".minigrep-index";
+const MAGIC: &[u8; 8] = b"MGINDEX\0";
+const FORMAT_VERSION: u32 = 2;
+
+// Full case folding, as -i does it.
+const FOLDING: Folding = Folding { case: true, turkic: false, normalization: Normalization::None };
+
+type Trigram = [u8; 3];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    path: String,
+    modified: (u64, u32),
+    size: u64,
+    // False for binary files, archives and text that isn't UTF-8. Those
+    // are always searched.
+    indexed: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Index {
+    files: Vec<Entry>,
+    // The files each trigram appears in, by position in `files`.
+    postings: HashMap<Trigram, Vec<u32>>,
+}
+
+// What `Index::build` did.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BuildStats {
+    pub files: usize,
+    // Files that were new or had changed, and so were read again.
+    pub read: usize,
+}
+
+impl Index {
+    // Indexes every file a search of `root` would walk. Files whose size
+    // and modification time match `previous` keep their old trigrams
+    // instead of being read again.
+    pub fn build(
+        root: &Path,
+        previous: Option<&Index>,
+    ) -> (Index, BuildStats, Vec<walk::WalkError>) {
+        let (paths, errors) = walk::files(root, &walk::Filter::new(&[], &[]));
+        let reusable = previous.map(Index::trigrams_by_file).unwrap_or_default();
+        let mut index = Index::default();
+        let mut stats = BuildStats::default();
+
+        for path in paths {
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let entry = Entry {
+                path: relative_path(root, &path),
+                modified: modified(&metadata),
+                size: metadata.len(),
+                indexed: true,
+            };
+            let (entry, trigrams) = match reusable.get(entry.path.as_str()) {
+                Some((old, trigrams))
+                    if old.modified == entry.modified && old.size == entry.size =>
+                {
+                    ((*old).clone(), trigrams.clone())
+                }
+                _ => {
+                    stats.read += 1;
+                    match fs::read(&path).ok().and_then(|contents| file_trigrams(&contents)) {
+                        Some(trigrams) => (entry, trigrams),
+                        None => (Entry { indexed: false, ..entry }, HashSet::new()),
+                    }
+                }
+            };
+
+            let number = index.files.len() as u32;
+            for trigram in trigrams {
+                index.postings.entry(trigram).or_default().push(number);
+            }
+            index.files.push(entry);
+        }
+
+        stats.files = index.files.len();
+        (index, stats, errors)
+    }
+
+    // Each file's entry and trigrams, by path.
+    fn trigrams_by_file(&self) -> HashMap<&str, (&Entry, HashSet<Trigram>)> {
+        let mut files: HashMap<&str, (&Entry, HashSet<Trigram>)> =
+            self.files.iter().map(|entry| (entry.path.as_str(), (entry, HashSet::new()))).collect();
+        for (trigram, numbers) in &self.postings {
+            for &number in numbers {
+                let path = self.files[number as usize].path.as_str();
+                if let Some((_, trigrams)) = files.get_mut(path) {
+                    trigrams.insert(*trigram);
+                }
+            }
+        }
+        files
+    }
+
+    // Reads the index of `root`, if it has one in a format this version
+    // understands.
+    pub fn load(root: &Path) -> io::Result<Index> {
+        let data = fs::read(root.join(FILE_NAME))?;
+        Index::decode(&data).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "unreadable or outdated index")
+        })
+    }
+
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        replace::write_atomically(&root.join(FILE_NAME), &self.encode())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+
+        out.extend_from_slice(&(self.files.len() as u32).to_le_bytes());
+        for entry in &self.files {
+            out.extend_from_slice(&(entry.path.len() as u32).to_le_bytes());
+            out.extend_from_slice(entry.path.as_bytes());
+            out.extend_from_slice(&entry.modified.0.to_le_bytes());
+            out.extend_from_slice(&entry.modified.1.to_le_bytes());
+            out.extend_from_slice(&entry.size.to_le_bytes());
+            out.push(u8::from(entry.indexed));
+        }
+
+        // Sorted, so the same tree always gives the same file.
+        let mut trigrams: Vec<_> = self.postings.iter().collect();
+        trigrams.sort();
+        out.extend_from_slice(&(trigrams.len() as u32).to_le_bytes());
+        for (trigram, numbers) in trigrams {
+            out.extend_from_slice(trigram);
+            out.extend_from_slice(&(numbers.len() as u32).to_le_bytes());
+            for number in numbers {
+                out.extend_from_slice(&number.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    fn decode(data: &[u8]) -> Option<Index> {
+        let mut input = Input(data);
+        if input.take(MAGIC.len())? != MAGIC || input.u32()? != FORMAT_VERSION {
+            return None;
+        }
+        let mut index = Index::default();
+
+        for _ in 0..input.u32()? {
+            let len = input.u32()? as usize;
+            let path = String::from_utf8(input.take(len)?.to_vec()).ok()?;
+            let modified = (input.u64()?, input.u32()?);
+            let size = input.u64()?;
+            let indexed = input.take(1)?[0] != 0;
+            index.files.push(Entry { path, modified, size, indexed });
+        }
+        for _ in 0..input.u32()? {
+            let trigram: Trigram = input.take(3)?.try_into().ok()?;
+            let count = input.u32()? as usize;
+            let numbers = (0..count).map(|_| input.u32()).collect::<Option<Vec<u32>>>()?;
+            if numbers.iter().any(|&number| number as usize >= index.files.len()) {
+                return None;
+            }
+            index.postings.insert(trigram, numbers);
+        }
+
+        input.0.is_empty().then_some(index)
+    }
+
+    // Drops the files under `root` that can't match. `query` is what
+    // `query_trigrams` returned. Files the index doesn't know about, or
+    // that changed since it was built, are kept.
+    pub fn candidates(
+        &self,
+        root: &Path,
+        files: Vec<PathBuf>,
+        query: &[Vec<Trigram>],
+    ) -> Vec<PathBuf> {
+        let mut possible = HashSet::new();
+        for trigrams in query {
+            possible.extend(self.containing_all(trigrams));
+        }
+
+        let entries: HashMap<&str, (usize, &Entry)> = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.path.as_str(), (i, entry)))
+            .collect();
+        files
+            .into_iter()
+            .filter(|path| {
+                let Some(&(number, entry)) = entries.get(relative_path(root, path).as_str()) else {
+                    return true;
+                };
+                let fresh = fs::metadata(path).is_ok_and(|metadata| {
+                    modified(&metadata) == entry.modified && metadata.len() == entry.size
+                });
+                !fresh || !entry.indexed || possible.contains(&number)
+            })
+            .collect()
+    }
+
+    // Numbers of the indexed files that contain every one of `trigrams`.
+    fn containing_all(&self, trigrams: &[Trigram]) -> Vec<usize> {
+        let mut lists: Vec<&[u32]> = Vec::new();
+        for trigram in trigrams {
+            match self.postings.get(trigram) {
+                Some(numbers) => lists.push(numbers),
+                None => return Vec::new(),
+            }
+        }
+        let Some(shortest) = lists.iter().min_by_key(|numbers| numbers.len()) else {
+            return (0..self.files.len()).collect();
+        };
+
+        shortest
+            .iter()
+            .filter(|number| lists.iter().all(|numbers| numbers.binary_search(number).is_ok()))
+            .map(|&number| number as usize)
+            .collect()
+    }
+}
+
+// The trigrams a file has to contain to match, one list per pattern, or
+// None if the index can't narrow down this search. That's the case when a
+// line can match without containing its pattern's bytes, such as with
+// --fuzzy or most regular expressions, and when files without a match are
+// printed too.
+pub fn query_trigrams(config: &Config) -> Option<Vec<Vec<Trigram>>> {
+    let every_file = matches!(config.print.mode, Mode::Count | Mode::FilesWithoutMatch);
+    let bytes_differ = config.read.encoding.is_some_and(|encoding| encoding != Encoding::Utf8)
+        || config.normalization != Normalization::None
+        || config.turkic;
+    // A --query with NOT can select lines that hold none of its terms.
+    let any_line = config.invert || config.query.is_some();
+    if any_line || every_file || bytes_differ || config.fuzzy.is_some() {
+        return None;
+    }
+    if config.regex && config.patterns.iter().any(|pattern| pattern.contains(is_regex_syntax)) {
+        return None;
+    }
+    // The regex engine folds case one character at a time, which doesn't
+    // always agree with full folding beyond ASCII.
+    let simple_folding = config.regex && config.ignore_case;
+    if simple_folding && !config.patterns.iter().all(|pattern| pattern.is_ascii()) {
+        return None;
+    }
+
+    let query = config.patterns.iter().map(|pattern| {
+        let mut trigrams: Vec<Trigram> = text_trigrams(pattern).into_iter().collect();
+        trigrams.sort();
+        trigrams
+    });
+    Some(query.collect())
+}
+
+fn is_regex_syntax(c: char) -> bool {
+    "\\.^$*+?()[]{}|".contains(c)
+}
+
+// The trigrams of a file's contents, or None if it has to be searched
+// every time because its bytes aren't the text that gets matched.
+fn file_trigrams(contents: &[u8]) -> Option<HashSet<Trigram>> {
+    let text = match Encoding::sniff(contents) {
+        None => contents,
+        Some((Encoding::Utf8, len)) => &contents[len..],
+        Some(_) => return None,
+    };
+    if walk::is_binary(text) || archive::is_archive(text) {
+        return None;
+    }
+    std::str::from_utf8(text).ok().map(text_trigrams)
+}
+
+// Trigrams are taken after full case folding, the same the matcher does
+// for -i, so the same index serves searches with and without it: "oﬀ"
+// is indexed as "off". Text that contains a pattern still contains it
+// once both are folded.
+fn text_trigrams(text: &str) -> HashSet<Trigram> {
+    let folded: String = FOLDING.chars(text).into_iter().collect();
+    folded.as_bytes().windows(3).map(|window| [window[0], window[1], window[2]]).collect()
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let parts: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+    parts.join("/")
+}
+
+fn modified(metadata: &fs::Metadata) -> (u64, u32) {
+    let since_epoch =
+        metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+    since_epoch.map_or((0, 0), |duration| (duration.as_secs(), duration.subsec_nanos()))
+}
+
+// Reads the fields of an index file in order.
+struct Input<'a>(&'a [u8]);
+
+impl<'a> Input<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    fn query(args: &[&str]) -> Option<Vec<Vec<Trigram>>> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        query_trigrams(&Config::build(&args).unwrap())
+    }
+
+    #[test]
+    fn builds_updates_and_narrows() {
+        let dir = TempDir::new("index");
+        let root = dir.path();
+        dir.write("src/main.rs", "fn main() {\n    serve();\n}\n");
+        dir.write("src/lib.rs", "pub fn serve() {}\n");
+        dir.write("README", "How To Run\n");
+        dir.write("notes.txt", "switch it oﬀ\n");
+
+        let (index, stats, _) = Index::build(root, None);
+        assert_eq!(BuildStats { files: 4, read: 4 }, stats);
+        index.save(root).unwrap();
+        let index = Index::load(root).unwrap();
+
+        let (files, _) = walk::files(root, &walk::Filter::new(&[], &[]));
+        let narrowed = |index: &Index, args: &[&str]| {
+            let query = query(args).unwrap();
+            let found = index.candidates(root, files.clone(), &query);
+            found.iter().map(|path| relative_path(root, path)).collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["src/lib.rs"], narrowed(&index, &["minigrep", "pub fn"]));
+        assert_eq!(vec!["README"], narrowed(&index, &["minigrep", "-i", "how to"]));
+        // The ligature folds to "ff", as it does when matching.
+        assert_eq!(vec!["notes.txt"], narrowed(&index, &["minigrep", "-i", "OFF"]));
+        assert_eq!(
+            vec!["README", "src/lib.rs"],
+            narrowed(&index, &["minigrep", "-e", "pub", "-e", "Run"])
+        );
+
+        // A changed file is searched even though the index says otherwise.
+        dir.write("README", "pub fn docs() {}\n");
+        assert_eq!(vec!["README", "src/lib.rs"], narrowed(&index, &["minigrep", "pub fn"]));
+
+        // Rebuilding only reads what changed.
+        let (index, stats, _) = Index::build(root, Some(&index));
+        assert_eq!(BuildStats { files: 4, read: 1 }, stats);
+        assert_eq!(vec!["README", "src/lib.rs"], narrowed(&index, &["minigrep", "pub fn"]));
+    }
+
+    #[test]
+    fn queries_that_cannot_narrow() {
+        assert_eq!(None, query(&["minigrep", "-v", "main"]));
+        assert_eq!(None, query(&["minigrep", "-c", "main"]));
+        assert_eq!(None, query(&["minigrep", "--fuzzy", "1", "main"]));
+        assert_eq!(None, query(&["minigrep", "-E", "ma+in"]));
+        assert_eq!(Some(vec![vec![*b"ain", *b"mai"]]), query(&["minigrep", "-E", "main"]));
+        assert_eq!(Some(vec![vec![*b"ain", *b"mai"]]), query(&["minigrep", "-i", "MAIN"]));
+        assert_eq!(Some(vec![vec![*b"des", *b"esk"]]), query(&["minigrep", "-i", "DESK"]));
+        assert_eq!(None, query(&["minigrep", "-E", "-i", "été"]));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let index = Index::default();
+        let mut data = index.encode();
+        assert_eq!(Some(Index::default()), Index::decode(&data));
+
+        data[8] = 1;
+        assert_eq!(None, Index::decode(&data));
+    }
+}
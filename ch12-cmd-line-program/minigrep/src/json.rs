@@ -0,0 +1,90 @@
+// Just enough JSON to write minigrep's machine-readable output: objects
+// are built up field by field and rendered on a single line.
+
+use std::fmt::Write;
+
+#[derive(Default)]
+pub struct Object {
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Object {
+    pub fn new() -> Object {
+        Object::default()
+    }
+
+    pub fn string(mut self, key: &'static str, value: &str) -> Object {
+        self.fields.push((key, quote(value)));
+        self
+    }
+
+    pub fn number(mut self, key: &'static str, value: impl ToString) -> Object {
+        self.fields.push((key, value.to_string()));
+        self
+    }
+
+    pub fn boolean(mut self, key: &'static str, value: bool) -> Object {
+        self.fields.push((key, value.to_string()));
+        self
+    }
+
+    pub fn object(mut self, key: &'static str, value: Object) -> Object {
+        self.fields.push((key, value.render()));
+        self
+    }
+
+    pub fn array(mut self, key: &'static str, values: Vec<Object>) -> Object {
+        let values: Vec<String> = values.into_iter().map(Object::render).collect();
+        self.fields.push((key, format!("[{}]", values.join(","))));
+        self
+    }
+
+    pub fn render(self) -> String {
+        let fields: Vec<String> = self
+            .fields
+            .into_iter()
+            .map(|(key, value)| format!("{}:{value}", quote(key)))
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+// Quotes and escapes `text` as a JSON string.
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_nested_objects() {
+        let object = Object::new()
+            .string("type", "match")
+            .number("line", 3)
+            .array("spans", vec![Object::new().number("start", 0)])
+            .object("data", Object::new().string("text", "tab\there \"quoted\" \u{1}"));
+
+        assert_eq!(
+            r#"{"type":"match","line":3,"spans":[{"start":0}],"data":{"text":"tab\there \"quoted\" \u0001"}}"#,
+            object.render()
+        );
+    }
+}
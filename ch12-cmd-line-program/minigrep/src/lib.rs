@@ -1,72 +1,758 @@
-use std::fs;
-use std::error::Error;
-use std::env;
+use std::fs::File;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path)?;
+pub mod aho_corasick;
+pub mod archive;
+mod config;
+pub mod encoding;
+mod error;
+pub mod fuzzy;
+pub mod glob;
+pub mod ignore;
+pub mod index;
+pub mod json;
+pub mod matcher;
+pub mod output;
+pub mod pool;
+pub mod query;
+pub mod regex;
+pub mod replace;
+mod searcher;
+pub mod stats;
+#[cfg(test)]
+mod temp;
+pub mod tui;
+pub mod unicode;
+pub mod walk;
+pub mod watch;
+
+pub use config::{ColorChoice, Config, ConfigError};
+pub use error::MinigrepError;
+pub use searcher::{
+    search_multiline, search_reader, Count, Match, Matches, ReadOptions, Searcher, SearcherBuilder,
+    Sink, Stream,
+};
+use encoding::Encoding;
+use index::Index;
+use matcher::Matcher;
+use output::{Mode, Printer, Summary};
+use regex::Regex;
+use stats::{Meter, Metered, Stats, Timed};
+
+// Returns whether anything was found: a matching line, or with -L a file
+// without one. Unreadable paths are reported as they come up and turn into
+// an error once everything else has been searched.
+pub fn run(config: Config) -> Result<bool, MinigrepError> {
+    let started = Instant::now();
+    let searcher = Searcher::new(&config)?;
+    let filter = walk::Filter::new(&config.include, &config.exclude)
+        .hidden(config.hidden)
+        .ignore_files(!config.no_ignore);
+    let mut totals = Totals::default();
+    let sources = collect_sources(&config, &filter, &mut totals.errors);
+    let walking = started.elapsed();
+
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    };
+    if config.in_place || config.dry_run {
+        let changed = rewrite_sources(
+            searcher.matcher(),
+            &sources,
+            threads,
+            config.dry_run,
+            &mut totals.errors,
+        )?;
+        if totals.errors > 0 {
+            return Err(MinigrepError::Unrewritable(totals.errors));
+        }
+        return Ok(changed);
+    }
 
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
+    let with_filename = config.paths.len() > 1 || sources.iter().any(Source::is_walked);
+    let mut print = config.print;
+    print.color = config.color.enabled(io::stdout().is_terminal()) && !print.json;
+
+    if config.follow {
+        return follow(&searcher, &sources, with_filename, print);
+    }
+    if config.interactive {
+        return tui::run(config, &source_paths(&sources));
+    }
+
+    let mut snapshot = watch::Snapshot::default();
+    if config.watch {
+        snapshot.update(&source_paths(&sources));
+    }
+    search_sources(&searcher, &sources, with_filename, print, threads, &mut totals)?;
+    if totals.closed {
+        return Ok(totals.found);
+    }
+    if config.watch {
+        // Runs until interrupted.
+        let mut waiter = watch::Waiter::new();
+        loop {
+            for dir in watched_dirs(&config, &sources) {
+                waiter.watch(&dir);
+            }
+            waiter.wait();
+
+            let mut totals = Totals::default();
+            let sources = collect_sources(&config, &filter, &mut totals.errors);
+            let changed = snapshot.update(&source_paths(&sources));
+            let is_changed = |path: &Path| changed.iter().any(|changed| changed == path);
+            let changed: Vec<Source> =
+                sources.into_iter().filter(|source| source.path().is_some_and(is_changed)).collect();
+            search_sources(&searcher, &changed, with_filename, print, threads, &mut totals)?;
+            if totals.closed {
+                return Ok(totals.found);
+            }
+        }
+    }
+
+    let stats = config.stats.then(|| totals.stats(walking, started.elapsed()));
+    let written = if print.json {
+        totals.summary.write_json(&mut io::stdout().lock(), started.elapsed(), stats.as_ref())
+    } else if let Some(stats) = stats {
+        stats.write_report(&mut io::stdout().lock())
     } else {
-        search(&config.query, &contents)
+        Ok(())
     };
+    if let Err(err) = written {
+        if !is_closed_output(&err) {
+            return Err(err.into());
+        }
+    }
+    if totals.errors > 0 {
+        return Err(MinigrepError::Unsearchable(totals.errors));
+    }
+    Ok(totals.found)
+}
+
+// What searching some sources came to.
+#[derive(Default)]
+struct Totals {
+    found: bool,
+    summary: Summary,
+    errors: usize,
+    // What --stats reports, beyond the summary.
+    meter: Meter,
+    lines: usize,
+    searching: Duration,
+    printing: Duration,
+    // Standard output was closed, as by `| head`, so there's no point in
+    // searching any further.
+    closed: bool,
+}
+
+impl Totals {
+    fn stats(&self, walking: Duration, elapsed: Duration) -> Stats {
+        Stats {
+            files: self.summary.searched,
+            files_with_matches: self.summary.with_matches,
+            bytes: self.meter.bytes(),
+            lines: self.lines,
+            matches: self.summary.matches,
+            walking,
+            reading: self.meter.time(),
+            searching: self.searching,
+            printing: self.printing,
+            elapsed,
+        }
+    }
+}
 
-    for line in results {
-        println!("{line}");
+// Expands the paths on the command line into what to search, walking
+// directories. Paths that can't be walked are reported and counted.
+fn collect_sources(config: &Config, filter: &walk::Filter, errors: &mut usize) -> Vec<Source> {
+    let query = if config.index { index::query_trigrams(config) } else { None };
+    let mut sources = Vec::new();
+
+    for path in &config.paths {
+        if path == "-" {
+            sources.push(Source::Stdin);
+            continue;
+        }
+
+        let path = PathBuf::from(path);
+        if path.is_dir() {
+            let (mut files, failed) = walk::files(&path, filter);
+            for (path, err) in failed {
+                eprintln!("minigrep: {}: {err}", path.display());
+                *errors += 1;
+            }
+            if let Some(query) = &query {
+                match Index::load(&path) {
+                    Ok(index) => files = index.candidates(&path, files, query),
+                    Err(err) => eprintln!("minigrep: {}: not using index: {err}", path.display()),
+                }
+            }
+            sources.extend(files.into_iter().map(Source::Walked));
+        } else {
+            sources.push(Source::File(path));
+        }
     }
 
-    Ok(())
+    sources
 }
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let mut results = Vec::new();
+// Searches `sources` and prints what was found, adding to `totals`. Whether
+// something was found means a matching line, or with -L a file without one.
+fn search_sources(
+    searcher: &Searcher,
+    sources: &[Source],
+    with_filename: bool,
+    print: output::PrintOptions,
+    threads: usize,
+    totals: &mut Totals,
+) -> io::Result<()> {
+    let found_in = |matches: usize| match print.mode {
+        Mode::FilesWithoutMatch => matches == 0,
+        _ => matches > 0,
+    };
 
-    for line in contents.lines() {
-        if line.contains(query) {
-            results.push(line);
+    if let [source] = sources {
+        // Nothing to run in parallel, so stream straight to stdout.
+        let mut printer = Printer::new(Timed::new(io::stdout().lock()), print);
+        let started = Instant::now();
+        let searched = search_source(searcher, source, with_filename, &totals.meter, &mut printer);
+        totals.lines += printer.lines_examined();
+        let matches_so_far = printer.matches();
+        let printing = printer.into_inner().spent;
+        totals.printing += printing;
+        totals.searching += started.elapsed().saturating_sub(printing);
+        match searched {
+            Ok(matches) => {
+                totals.found |= found_in(matches);
+                totals.summary.add(matches);
+            }
+            Err(err) if is_closed_output(&err) => {
+                totals.found |= found_in(matches_so_far);
+                totals.closed = true;
+            }
+            Err(err) => {
+                eprintln!("minigrep: {source}: {err}");
+                totals.errors += 1;
+            }
         }
+        return Ok(());
     }
 
-    results
+    let separate = !print.json && (print.before_context > 0 || print.after_context > 0);
+    let mut out = Timed::new(io::stdout().lock());
+    let mut printed_any = false;
+    // Whether the file being written out has printed anything yet.
+    let mut printing_file = false;
+    // Set when stdout is closed partway through a file. The rest of its
+    // output is dropped, but its matches still count toward the status.
+    let mut closed = None;
+
+    // Each file's output is written out in order, as soon as the files
+    // before it are done.
+    let meter = &totals.meter;
+    let printed = pool::for_each_streamed(
+        sources,
+        threads,
+        |source, output| {
+            let mut printer = Printer::new(Timed::new(output), print);
+            let started = Instant::now();
+            let matches = search_source(searcher, source, with_filename, meter, &mut printer)?;
+            let lines = printer.lines_examined();
+            // Not counting the wait for the files ahead of this one.
+            let searching = started.elapsed().saturating_sub(printer.into_inner().spent);
+            Ok::<_, io::Error>((matches, lines, searching))
+        },
+        |source, event| {
+            let result = match event {
+                pool::Event::Output(_) if closed.is_some() => return Ok(()),
+                pool::Event::Output(output) => {
+                    let mut written = Ok(());
+                    if separate && printed_any && !printing_file {
+                        written = writeln!(out, "{}", output::separator(&print));
+                    }
+                    printing_file = true;
+                    if let Err(err) = written.and_then(|()| out.write_all(&output)) {
+                        if !is_closed_output(&err) {
+                            return Err(err);
+                        }
+                        closed = Some(err);
+                    }
+                    return Ok(());
+                }
+                pool::Event::Done(result) => result,
+            };
+            printed_any |= std::mem::take(&mut printing_file);
+            match result {
+                Ok((matches, lines, searching)) => {
+                    totals.found |= found_in(matches);
+                    totals.summary.add(matches);
+                    totals.lines += lines;
+                    totals.searching += searching;
+                }
+                // Stops the pool, and is then taken for the end of the output.
+                Err(err) if is_closed_output(&err) => return Err(err),
+                Err(err) => {
+                    eprintln!("minigrep: {source}: {err}");
+                    totals.errors += 1;
+                }
+            }
+            closed.take().map_or(Ok(()), Err)
+        },
+    );
+    totals.printing += out.spent;
+    match printed {
+        Err(err) if is_closed_output(&err) => {
+            totals.closed = true;
+            Ok(())
+        }
+        printed => printed,
+    }
 }
 
-pub fn search_case_insensitive<'a>(query: &str,contents: &'a str,) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    let mut results = Vec::new();
+// Reading never fails this way, so this is a write to standard output
+// after whatever reads it, such as `head`, has exited.
+fn is_closed_output(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::BrokenPipe
+}
 
-    for line in contents.lines() {
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
+// Prints matches among the lines appended to each file until interrupted,
+// for --follow, or until -m is reached in every file. Standard input is
+// simply searched as it arrives. Returns whether anything matched.
+fn follow(
+    searcher: &Searcher,
+    sources: &[Source],
+    with_filename: bool,
+    print: output::PrintOptions,
+) -> Result<bool, MinigrepError> {
+    let terminator = searcher.read_options().terminator();
+    let mut tails = Vec::new();
+    let mut found = false;
+    for source in sources {
+        let mut printer = Printer::new(io::stdout(), print);
+        let opened = match source {
+            Source::Stdin => {
+                search_source(searcher, source, with_filename, &Meter::default(), &mut printer)
+                    .map(|matches| found |= matches > 0)
+            }
+            Source::File(path) | Source::Walked(path) => watch::Tail::open(path, terminator)
+                .map(|tail| tails.push((tail, printer))),
+        };
+        match opened {
+            Ok(()) => {}
+            Err(err) if is_closed_output(&err) => return Ok(found),
+            Err(err) => {
+                eprintln!("minigrep: {source}: {err}");
+                return Err(MinigrepError::Unsearchable(1));
+            }
         }
     }
+    for (tail, printer) in &mut tails {
+        printer.begin_file(&tail.path().display().to_string(), with_filename);
+    }
 
-    results
+    let mut waiter = watch::Waiter::new();
+    while !tails.is_empty() {
+        // Files with more to read are read again before waiting.
+        let mut busy = false;
+        for (tail, printer) in &mut tails {
+            if let Some(dir) = tail.path().parent() {
+                // The directory, so that a file renamed into place is noticed.
+                waiter.watch(if dir.as_os_str().is_empty() { Path::new(".") } else { dir });
+            }
+
+            let name = tail.path().display().to_string();
+            let events = tail.poll().map_err(|err| {
+                eprintln!("minigrep: {name}: {err}");
+                MinigrepError::Unsearchable(1)
+            })?;
+            busy |= !events.is_empty();
+            for event in events {
+                match event {
+                    watch::Event::Lines { first_line, offset, bytes } => {
+                        let lines = first_line - 1;
+                        let mut sink = Shifted { sink: &mut *printer, lines, offset };
+                        let matcher = searcher.matcher();
+                        let searched = search_reader(matcher, &bytes[..], terminator, &mut sink);
+                        found |= printer.matches() > 0;
+                        match searched {
+                            Ok(()) => {}
+                            Err(err) if is_closed_output(&err) => return Ok(found),
+                            Err(err) => return Err(err.into()),
+                        }
+                        if printer.is_done() {
+                            // -m was reached for this file.
+                            break;
+                        }
+                    }
+                    watch::Event::Truncated => {
+                        eprintln!("minigrep: {name}: file truncated");
+                        printer.begin_file(&name, with_filename);
+                    }
+                    watch::Event::Replaced => {
+                        eprintln!("minigrep: {name}: file replaced, following the new file");
+                        printer.begin_file(&name, with_filename);
+                    }
+                }
+            }
+        }
+        tails.retain(|(_, printer)| !printer.is_done());
+        if !busy {
+            waiter.wait();
+        }
+    }
+    Ok(found)
 }
 
-pub struct Config {
-    pub query: String,
-    pub file_path: String,
-    pub ignore_case: bool,
+// Passes lines on to another sink as if they came further into the file:
+// `lines` lines and `offset` bytes later.
+struct Shifted<'s, S> {
+    sink: &'s mut S,
+    lines: usize,
+    offset: usize,
 }
 
-impl Config {
-    pub fn build(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            return Err("not enough arguments");
+impl<S: Sink> Shifted<'_, S> {
+    fn shift<'a>(&self, line: &Line<'a>) -> Line<'a> {
+        let mut line = line.clone();
+        line.number += self.lines;
+        line.end_number += self.lines;
+        line.offset += self.offset;
+        line
+    }
+}
+
+impl<S: Sink> Sink for Shifted<'_, S> {
+    fn matched(&mut self, line: &Line) -> io::Result<bool> {
+        let line = self.shift(line);
+        self.sink.matched(&line)
+    }
+
+    fn context(&mut self, line: &Line) -> io::Result<bool> {
+        let line = self.shift(line);
+        self.sink.context(&line)
+    }
+}
+
+fn source_paths(sources: &[Source]) -> Vec<PathBuf> {
+    sources.iter().filter_map(Source::path).map(Path::to_path_buf).collect()
+}
+
+// The directories --watch asks to be woken up for: those named on the
+// command line, the directories of named files, and every directory a
+// walk found a file in.
+fn watched_dirs(config: &Config, sources: &[Source]) -> Vec<PathBuf> {
+    let named = config.paths.iter().filter(|path| *path != "-").map(PathBuf::from);
+    let mut dirs: Vec<PathBuf> = named.filter(|path| path.is_dir()).collect();
+    for path in sources.iter().filter_map(Source::path) {
+        match path.parent() {
+            Some(dir) if dir.as_os_str().is_empty() => dirs.push(PathBuf::from(".")),
+            Some(dir) => dirs.push(dir.to_path_buf()),
+            None => {}
         }
+    }
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
 
-        let query = args[1].clone();
-        let file_path = args[2].clone();
+// Creates or updates the index of each directory in `dirs`, for
+// `minigrep index build`.
+pub fn build_indexes(dirs: &[String]) -> Result<(), MinigrepError> {
+    for dir in dirs {
+        let root = Path::new(dir);
+        if !root.is_dir() {
+            return Err(MinigrepError::NotADirectory(dir.clone()));
+        }
+        // An index in an older format is simply rebuilt.
+        let previous = Index::load(root).ok();
+        let (index, stats, failed) = Index::build(root, previous.as_ref());
+        for (path, err) in failed {
+            eprintln!("minigrep: {}: {err}", path.display());
+        }
+        index.save(root)?;
+        println!("{dir}: indexed {} files, read {}", stats.files, stats.read);
+    }
+    Ok(())
+}
+
+enum Source {
+    Stdin,
+    // Named on the command line.
+    File(PathBuf),
+    // Found by walking a directory named on the command line.
+    Walked(PathBuf),
+}
+
+impl Source {
+    fn is_walked(&self) -> bool {
+        matches!(self, Source::Walked(_))
+    }
 
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+    fn path(&self) -> Option<&Path> {
+        match self {
+            Source::Stdin => None,
+            Source::File(path) | Source::Walked(path) => Some(path),
+        }
+    }
+}
 
-        Ok(Config { query, file_path, ignore_case })
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Stdin => write!(f, "(standard input)"),
+            Source::File(path) | Source::Walked(path) => write!(f, "{}", path.display()),
+        }
     }
 }
 
+// Applies the replacement to every file, either rewriting them or, for a
+// dry run, printing a diff of what would change. Returns whether anything
+// changed.
+fn rewrite_sources(
+    matcher: &Matcher,
+    sources: &[Source],
+    threads: usize,
+    dry_run: bool,
+    errors: &mut usize,
+) -> io::Result<bool> {
+    let mut changed = false;
+    let mut out = io::stdout().lock();
+
+    pool::for_each_ordered(
+        sources,
+        threads,
+        |source| rewrite_source(matcher, source, dry_run),
+        |source, result| {
+            match result {
+                Ok(Some(diff)) => {
+                    changed = true;
+                    out.write_all(diff.as_bytes())?;
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    eprintln!("minigrep: {source}: {err}");
+                    *errors += 1;
+                }
+            }
+            Ok::<(), io::Error>(())
+        },
+    )?;
+
+    Ok(changed)
+}
+
+// Rewrites one file. Returns None if nothing in it matched, or else the
+// diff for a dry run and an empty string otherwise.
+fn rewrite_source(matcher: &Matcher, source: &Source, dry_run: bool) -> io::Result<Option<String>> {
+    let path = match source {
+        Source::Stdin => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "can't rewrite standard input"));
+        }
+        Source::File(path) | Source::Walked(path) => path,
+    };
+
+    let bytes = std::fs::read(path)?;
+    if source.is_walked() && (walk::is_binary(&bytes) || archive::is_archive(&bytes)) {
+        return Ok(None);
+    }
+    if archive::is_archive(&bytes) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "can't rewrite archives"));
+    }
+    // A UTF-8 byte order mark is kept, but isn't part of the first line.
+    let bom = match Encoding::sniff(&bytes) {
+        None => 0,
+        Some((Encoding::Utf8, len)) => len,
+        Some(_) => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "only UTF-8 files can be rewritten"));
+        }
+    };
+    // Rewriting lossily decoded text would corrupt the file.
+    let contents = std::str::from_utf8(&bytes[bom..])
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not valid UTF-8"))?;
+
+    let (rewritten, changes) = replace::rewrite(matcher, contents);
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    if dry_run {
+        return Ok(Some(replace::diff(&source.to_string(), &changes)));
+    }
+    replace::write_atomically(path, &[&bytes[..bom], rewritten.as_bytes()].concat())?;
+    Ok(Some(String::new()))
+}
+
+// Searches one source and returns its number of matching lines.
+// What is read is recorded in `meter`.
+fn search_source<W: Write>(
+    searcher: &Searcher,
+    source: &Source,
+    with_filename: bool,
+    meter: &Meter,
+    printer: &mut Printer<W>,
+) -> io::Result<usize> {
+    let name = source.to_string();
+
+    match source {
+        Source::Stdin => {
+            let reader = BufReader::new(Metered::new(io::stdin().lock(), meter));
+            search_input(searcher, reader, &name, with_filename, false, printer)
+        }
+        Source::File(path) | Source::Walked(path) => {
+            let reader = BufReader::new(Metered::new(File::open(path)?, meter));
+            search_input(searcher, reader, &name, with_filename, source.is_walked(), printer)
+        }
+    }
+}
+
+// Searches one input, looking inside it if it is compressed or an archive.
+// Binary files are skipped if `skip_binary` is set, as they are when
+// walking a directory, and otherwise only reported if they match.
+fn search_input<R: BufRead, W: Write>(
+    searcher: &Searcher,
+    reader: R,
+    name: &str,
+    with_filename: bool,
+    skip_binary: bool,
+    printer: &mut Printer<W>,
+) -> io::Result<usize> {
+    // A pipe can return less at a time than it takes to recognize a tar
+    // archive or a binary file, so read that much up front.
+    let reader = archive::read_ahead(reader, walk::BINARY_SNIFF_LEN)?;
+    if !archive::is_archive(reader.get_ref().0.get_ref()) {
+        return search_text(searcher, reader, name, with_filename, skip_binary, printer);
+    }
+
+    // Every member is searched as a file of its own, named "archive:member".
+    let mut matches = 0;
+    archive::for_each_member(reader, &mut |path, contents| {
+        matches += match path {
+            Some(path) => {
+                let name = format!("{name}:{path}");
+                search_text(searcher, contents, &name, true, true, printer)?
+            }
+            None => search_text(searcher, contents, name, with_filename, skip_binary, printer)?,
+        };
+        Ok(())
+    })?;
+
+    Ok(matches)
+}
+
+// Searches text in whichever encoding its byte order mark names, or else
+// in the one the searcher was given, defaulting to UTF-8.
+fn search_text<R: BufRead, W: Write>(
+    searcher: &Searcher,
+    reader: R,
+    name: &str,
+    with_filename: bool,
+    skip_binary: bool,
+    printer: &mut Printer<W>,
+) -> io::Result<usize> {
+    let (reader, binary) = searcher.decode(reader)?;
+    if binary && skip_binary {
+        return Ok(0);
+    }
+
+    printer.begin_file(name, with_filename);
+    if binary {
+        printer.binary_file();
+    }
+    searcher.search_decoded(reader, printer)?;
+    printer.end_file()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line<'a> {
+    // 1-based line number.
+    pub number: usize,
+    // Byte offset of the start of the line within the searched text.
+    pub offset: usize,
+    // The line without its line ending. A multiline match (-U) covers
+    // several lines, joined by their line endings.
+    pub text: &'a str,
+    // Byte ranges of the matches within `text`. Empty for lines that
+    // weren't matched, and for lines selected by an inverted match.
+    pub spans: Vec<Range<usize>>,
+    // The number of the last line a multiline match covers; otherwise the
+    // same as `number`.
+    pub end_number: usize,
+}
+
+impl<'a> Line<'a> {
+    pub fn new(number: usize, offset: usize, text: &'a str, spans: Vec<Range<usize>>) -> Line<'a> {
+        Line { number, offset, text, spans, end_number: number }
+    }
+}
+
+// Splits `contents` into lines like str::lines, but keeps track of where
+// each line starts.
+pub fn lines(contents: &str) -> impl Iterator<Item = Line<'_>> {
+    let mut offset = 0;
+
+    contents.split_inclusive('\n').enumerate().map(move |(i, raw)| {
+        let text = trim_line_ending(raw, b'\n');
+        let line = Line::new(i + 1, offset, text, Vec::new());
+        offset += raw.len();
+        line
+    })
+}
+
+pub(crate) fn trim_line_ending(raw: &str, terminator: u8) -> &str {
+    match raw.strip_suffix(char::from(terminator)) {
+        Some(text) if terminator == b'\n' => text.strip_suffix('\r').unwrap_or(text),
+        Some(text) => text,
+        None => raw,
+    }
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<Line<'a>> {
+    search_with(&Matcher::literal(query), contents)
+}
+
+pub fn search_case_insensitive<'a>(query: &str,contents: &'a str,) -> Vec<Line<'a>> {
+    search_with(&Matcher::case_insensitive(query), contents)
+}
+
+pub fn search_regex<'a>(regex: &Regex, contents: &'a str) -> Vec<Line<'a>> {
+    search_with(&Matcher::regex(regex.clone()), contents)
+}
+
+pub fn search_with<'a>(matcher: &Matcher, contents: &'a str) -> Vec<Line<'a>> {
+    let mut results = Vec::new();
+
+    for mut line in lines(contents) {
+        if let Some(spans) = matcher.match_line(line.text) {
+            line.spans = spans;
+            results.push(line);
+        }
+    }
+
+    results
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use matcher::Boundary;
+
+    fn texts<'a>(lines: Vec<Line<'a>>) -> Vec<&'a str> {
+        lines.iter().map(|line| line.text).collect()
+    }
+
+    #[test]
+    fn lines_keep_offsets() {
+        let lines: Vec<_> = lines("one\r\ntwo\n\nfour").collect();
+
+        assert_eq!(4, lines.len());
+        assert_eq!(Line::new(2, 5, "two", vec![]), lines[1]);
+        assert_eq!(Line::new(4, 10, "four", vec![]), lines[3]);
+    }
 
     #[test]
     fn case_sensitive() {
@@ -76,7 +762,10 @@ Rust:
 safe, fast, productive.
 Pick three.";
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        assert_eq!(
+            vec![Line::new(2, 6, "safe, fast, productive.", vec![Range { start: 15, end: 19 }])],
+            search(query, contents)
+        );
     }
 
     #[test]
@@ -90,7 +779,177 @@ Trust me.";
 
         assert_eq!(
             vec!["Rust:", "Trust me."],
-            search_case_insensitive(query, contents)
+            texts(search_case_insensitive(query, contents))
+        );
+    }
+
+    #[test]
+    fn whole_words() {
+        let contents = "\
+Rust:
+Trust me.
+rust belt";
+        let exact = Matcher::literal("Rust").boundary(Boundary::Word);
+        let folded = Matcher::case_insensitive("rust").boundary(Boundary::Word);
+
+        assert_eq!(vec!["Rust:"], texts(search_with(&exact, contents)));
+        assert_eq!(vec!["Rust:", "rust belt"], texts(search_with(&folded, contents)));
+    }
+
+    #[test]
+    fn regex() {
+        let regex = Regex::new(r"^ERROR.*\d$").unwrap();
+        let contents = "\
+ERROR disk full at sector 42
+ERROR disk full
+INFO retry 3";
+
+        assert_eq!(
+            vec!["ERROR disk full at sector 42"],
+            texts(search_regex(&regex, contents))
+        );
+    }
+
+    #[test]
+    fn reader_prints_replacements() {
+        let regex = Regex::new(r"(\w+)\.unwrap\(\)").unwrap();
+        let matcher = Matcher::regex(regex).replacement(replace::Replacement::new("$1?"));
+        let contents = "let a = x.unwrap();\nlet b = y;\n";
+        let mut printer = Printer::new(Vec::new(), Default::default());
+
+        search_reader(&matcher, contents.as_bytes(), b'\n', &mut printer).unwrap();
+
+        assert_eq!("let a = x?;\n", String::from_utf8(printer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn archive_members_are_named() {
+        // A tar archive with one member, compressed with gzip.
+        let mut tar = vec![0u8; 512];
+        tar[..7].copy_from_slice(b"app.log");
+        tar[124..135].copy_from_slice(b"00000000016");
+        tar[156] = b'0';
+        tar[257..263].copy_from_slice(b"ustar\0");
+        tar.extend_from_slice(b"ok\nERROR full\n");
+        tar.resize(512 * 4, 0);
+        let gzipped = gzip_stored(&tar);
+
+        let options = output::PrintOptions { line_number: true, ..Default::default() };
+        let mut printer = Printer::new(Vec::new(), options);
+        let searcher = SearcherBuilder::new("ERROR").build().unwrap();
+        let matches =
+            search_input(&searcher, &gzipped[..], "logs.tar.gz", false, false, &mut printer).unwrap();
+
+        assert_eq!(1, matches);
+        assert_eq!(
+            "logs.tar.gz:app.log:2:ERROR full\n",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    // Wraps `data` in gzip using uncompressed deflate blocks.
+    fn gzip_stored(data: &[u8]) -> Vec<u8> {
+        let mut gzipped = b"\x1f\x8b\x08\0\0\0\0\0\0\xff".to_vec();
+        let chunks: Vec<&[u8]> = data.chunks(u16::MAX as usize).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            gzipped.push(u8::from(i + 1 == chunks.len()));
+            let len = chunk.len() as u16;
+            gzipped.extend_from_slice(&len.to_le_bytes());
+            gzipped.extend_from_slice(&(!len).to_le_bytes());
+            gzipped.extend_from_slice(chunk);
+        }
+        gzipped.extend_from_slice(&archive::crc32(data).to_le_bytes());
+        gzipped.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        gzipped
+    }
+
+    #[test]
+    fn encodings_are_decoded() {
+        let search = |contents: &[u8], encoding| {
+            let options = output::PrintOptions { line_number: true, ..Default::default() };
+            let mut printer = Printer::new(Vec::new(), options);
+            let read = ReadOptions { encoding, ..Default::default() };
+            let searcher = Searcher::with_matcher(Matcher::literal("señal"), read);
+            search_text(&searcher, contents, "in", false, false, &mut printer).unwrap();
+            String::from_utf8(printer.into_inner()).unwrap()
+        };
+
+        let utf16: Vec<u8> =
+            "\u{feff}uno\r\nseñal\r\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!("2:señal\n", search(&utf16, None));
+        assert_eq!("2:señal\n", search(b"uno\nse\xf1al\n", Some(Encoding::Latin1)));
+        assert_eq!("1:señal\n", search("\u{feff}señal".as_bytes(), Some(Encoding::Latin1)));
+        assert_eq!("", search(b"uno\nse\xf1al\n", None));
+    }
+
+    #[test]
+    fn binary_files_are_reported() {
+        let searcher = SearcherBuilder::new("main").build().unwrap();
+        let contents = b"\x7fELF\x02\x01\0\0main\0";
+        let search = |skip_binary| {
+            let mut printer = Printer::new(Vec::new(), Default::default());
+            let matches =
+                search_text(&searcher, &contents[..], "a.out", false, skip_binary, &mut printer)
+                    .unwrap();
+            (matches, String::from_utf8(printer.into_inner()).unwrap())
+        };
+
+        assert_eq!((1, "Binary file a.out matches\n".to_string()), search(false));
+        assert_eq!((0, String::new()), search(true));
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let matcher = Matcher::regex(Regex::new("^two$").unwrap());
+        let options = output::PrintOptions { byte_offset: true, ..Default::default() };
+        let mut printer = Printer::new(Vec::new(), options);
+
+        search_reader(&matcher, &b"one\r\ntwo\r\nthree\r\n"[..], b'\n', &mut printer).unwrap();
+
+        assert_eq!("5:two\n", String::from_utf8(printer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn multiline_matches_span_lines() {
+        let matcher = Matcher::regex(Regex::new(r"fn \w+\(\s+a").unwrap());
+        let contents = "// sum\r\nfn add(\r\n    a: i32,\r\n) {}\r\nfn main() {}\r\n";
+        let search = |options| {
+            let mut printer = Printer::new(Vec::new(), options);
+            search_multiline(&matcher, contents.as_bytes(), b'\n', &mut printer).unwrap();
+            String::from_utf8(printer.into_inner()).unwrap()
+        };
+
+        let options = output::PrintOptions { line_number: true, after_context: 1, ..Default::default() };
+        assert_eq!("2:fn add(\n3:    a: i32,\n4-) {}\n", search(options));
+
+        let options = output::PrintOptions { json: true, ..Default::default() };
+        let records = search(options);
+        assert!(records.contains(r#""line_number":2,"end_line_number":3,"byte_offset":8,"#));
+    }
+
+    #[test]
+    fn null_data_records() {
+        let matcher = Matcher::literal("two");
+        let options = output::PrintOptions { null_data: true, ..Default::default() };
+        let mut printer = Printer::new(Vec::new(), options);
+
+        search_reader(&matcher, &b"one\0two\nlines\0three"[..], b'\0', &mut printer).unwrap();
+
+        assert_eq!("two\nlines\0", String::from_utf8(printer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn reader_replaces_invalid_utf8() {
+        let matcher = Matcher::literal("fast");
+        let contents = b"Rust:\r\nsafe, \xff fast, productive.\nPick three.";
+        let options = output::PrintOptions { line_number: true, ..Default::default() };
+        let mut printer = Printer::new(Vec::new(), options);
+
+        search_reader(&matcher, &contents[..], b'\n', &mut printer).unwrap();
+
+        assert_eq!(
+            "2:safe, \u{fffd} fast, productive.\n",
+            String::from_utf8(printer.into_inner()).unwrap()
         );
     }
 }
@@ -1,20 +1,50 @@
 use std::env;
 use std::process;
-use minigrep::Config;
+use minigrep::{Config, ConfigError};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
+    // `minigrep index build DIR...` maintains indexes instead of searching.
+    let command: Vec<&str> = args.iter().skip(1).take(2).map(String::as_str).collect();
+    if command == ["index", "build"] {
+        if args.len() == 3 {
+            eprintln!("Usage: minigrep index build DIR...");
+            process::exit(2);
+        }
+        if let Err(e) = minigrep::build_indexes(&args[3..]) {
+            eprintln!("Application error: {e}");
+            process::exit(2);
+        }
+        return;
+    }
+
     let config = Config::build(&args).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+        match err {
+            ConfigError::HelpRequested => print!("{}", Config::help()),
+            ConfigError::VersionRequested => println!("{}", Config::version()),
+            _ => {
+                eprintln!("Problem parsing arguments: {}", err);
+                eprintln!("Try 'minigrep --help' for more information.");
+                process::exit(2);
+            }
+        }
+        process::exit(0);
     });
-    
+    if config.debug_config {
+        eprint!("{}", config.describe_settings());
+    }
+
     // println!("\nSearching for '{}'", config.query);
     // println!("In file {}\n", config.file_path);
 
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+    // Like grep: 0 if something was found, 1 if not, 2 on errors.
+    match minigrep::run(config) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Application error: {e}");
+            process::exit(2);
+        }
     }
 }
@@ -0,0 +1,458 @@
+// Decides whether a single line matches, for searches that look at one
+// line at a time instead of a whole file held in memory.
+//
+// Matches are reported as byte ranges into the line exactly as it was
+// read, so they can be highlighted even when the comparison itself was
+// done on case-folded or normalized text.
+
+use std::ops::Range;
+
+use crate::aho_corasick::AhoCorasick;
+use crate::error::MinigrepError;
+use crate::fuzzy;
+use crate::query::{self, Query};
+use crate::regex::{Regex, RegexBuilder};
+use crate::replace::Replacement;
+use crate::unicode::{self, Folding, Normalization, Unit};
+use crate::Config;
+
+pub struct Matcher {
+    kind: Kind,
+    boundary: Boundary,
+    // Select the lines that don't match instead.
+    invert: bool,
+    replacement: Option<Replacement>,
+    // Set when whole files are matched at once (-U): the character that
+    // ends the lines within them, which -x has to respect.
+    terminator: Option<char>,
+}
+
+// Where a match has to start and end to count.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Boundary {
+    #[default]
+    Anywhere,
+    // Not next to a letter, digit or underscore (-w).
+    Word,
+    // The match is the whole line (-x).
+    Line,
+}
+
+enum Kind {
+    // A single exact pattern.
+    Literal(String),
+    // Any number of exact patterns, searched for in one pass.
+    Literals(AhoCorasick<u8>),
+    // Patterns already folded the same way as the lines will be.
+    Folded(AhoCorasick<char>, Folding),
+    // Patterns within some number of edits, compared after folding.
+    Fuzzy(Vec<Vec<char>>, Folding, usize),
+    Regex(Regex),
+    // A boolean query (--query), with a matcher for each of its terms.
+    Query(Query, Vec<Matcher>),
+}
+
+impl Matcher {
+    pub fn new(config: &Config) -> Result<Matcher, MinigrepError> {
+        let patterns = &config.patterns;
+        let folding = Folding {
+            case: config.ignore_case,
+            turkic: config.turkic,
+            normalization: config.normalization,
+        };
+        let mut matcher = if let Some(text) = &config.query {
+            let (query, terms) = query::parse(text)?;
+            let terms = terms.iter().map(|term| {
+                let term = if config.ignore_case || config.normalization != Normalization::None {
+                    Matcher::folded(&[term], folding)
+                } else {
+                    Matcher::literal(term)
+                };
+                term.boundary(config.boundary)
+            });
+            Matcher::query(query, terms.collect())
+        } else if let Some(max_edits) = config.fuzzy {
+            Matcher::fuzzy(patterns, folding, max_edits)
+        } else if config.regex && !patterns.is_empty() {
+            let pattern = match patterns.as_slice() {
+                [pattern] => pattern.clone(),
+                // Grouped, so flags and alternatives stay within each pattern.
+                _ => patterns.iter().map(|p| format!("(?:{p})")).collect::<Vec<_>>().join("|"),
+            };
+            let regex = RegexBuilder::new(&pattern)
+                .case_insensitive(config.ignore_case)
+                .multi_line(config.read.multiline)
+                .build()?;
+            Matcher::regex(regex)
+        } else if config.ignore_case || config.normalization != Normalization::None {
+            Matcher::folded(patterns, folding)
+        } else if let [pattern] = patterns.as_slice() {
+            Matcher::literal(pattern)
+        } else {
+            Matcher::literals(patterns)
+        };
+        matcher.boundary = config.boundary;
+        matcher.invert = config.invert;
+        matcher.replacement = config.replace.as_deref().map(Replacement::new);
+        if config.read.multiline {
+            matcher.terminator = Some(if config.read.null_data { '\0' } else { '\n' });
+        }
+        Ok(matcher)
+    }
+
+    fn with_kind(kind: Kind) -> Matcher {
+        Matcher {
+            kind,
+            boundary: Boundary::default(),
+            invert: false,
+            replacement: None,
+            terminator: None,
+        }
+    }
+
+    pub fn literal(query: &str) -> Matcher {
+        Matcher::with_kind(Kind::Literal(query.to_string()))
+    }
+
+    // Matches a line containing any of `patterns`. With no patterns at
+    // all, nothing matches.
+    pub fn literals<S: AsRef<str>>(patterns: &[S]) -> Matcher {
+        let patterns: Vec<&[u8]> = patterns.iter().map(|p| p.as_ref().as_bytes()).collect();
+        Matcher::with_kind(Kind::Literals(AhoCorasick::new(&patterns)))
+    }
+
+    // Matches regardless of case, using full Unicode case folding.
+    pub fn case_insensitive(query: &str) -> Matcher {
+        Matcher::folded(&[query], Folding { case: true, ..Default::default() })
+    }
+
+    pub fn folded<S: AsRef<str>>(patterns: &[S], folding: Folding) -> Matcher {
+        let patterns: Vec<Vec<char>> = patterns.iter().map(|p| folding.chars(p.as_ref())).collect();
+        Matcher::with_kind(Kind::Folded(AhoCorasick::new(&patterns), folding))
+    }
+
+    // Matches anything within `max_edits` insertions, deletions or
+    // substitutions of one of `patterns`.
+    pub fn fuzzy<S: AsRef<str>>(patterns: &[S], folding: Folding, max_edits: usize) -> Matcher {
+        let patterns = patterns.iter().map(|p| folding.chars(p.as_ref())).collect();
+        Matcher::with_kind(Kind::Fuzzy(patterns, folding, max_edits))
+    }
+
+    pub fn regex(regex: Regex) -> Matcher {
+        Matcher::with_kind(Kind::Regex(regex))
+    }
+
+    // Matches lines for which `query` holds, where term i of the query
+    // holds if `terms[i]` matches.
+    pub fn query(query: Query, terms: Vec<Matcher>) -> Matcher {
+        Matcher::with_kind(Kind::Query(query, terms))
+    }
+
+    pub fn boundary(mut self, boundary: Boundary) -> Matcher {
+        self.boundary = boundary;
+        self
+    }
+
+    pub fn replacement(mut self, replacement: Replacement) -> Matcher {
+        self.replacement = Some(replacement);
+        self
+    }
+
+    // Matches text holding several lines ended by `terminator`, so that
+    // -x looks at the lines rather than the whole text.
+    pub fn multiline(mut self, terminator: char) -> Matcher {
+        self.terminator = Some(terminator);
+        self
+    }
+
+    pub fn is_inverted(&self) -> bool {
+        self.invert
+    }
+
+    // Substitutes the replacement for every span of a matched line. Returns
+    // the new line and the spans of the replaced text within it, or None if
+    // there's no replacement to make.
+    pub fn replace(&self, line: &str, spans: &[Range<usize>]) -> Option<(String, Vec<Range<usize>>)> {
+        let replacement = self.replacement.as_ref()?;
+        let mut replaced = String::with_capacity(line.len());
+        let mut new_spans = Vec::with_capacity(spans.len());
+        let mut end = 0;
+
+        for span in spans {
+            replaced.push_str(&line[end..span.start]);
+            let groups = match &self.kind {
+                Kind::Regex(regex) => regex.captures_at(line, span.start).unwrap_or_default(),
+                _ => vec![Some(span.clone())],
+            };
+            let start = replaced.len();
+            replaced.push_str(&replacement.expand(line, &groups));
+            new_spans.push(start..replaced.len());
+            end = span.end;
+        }
+        replaced.push_str(&line[end..]);
+
+        Some((replaced, new_spans))
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.match_line(line).is_some()
+    }
+
+    // Returns the spans to highlight if the line is selected, or None if
+    // it isn't. Inverted matches select lines without spans.
+    pub fn match_line(&self, line: &str) -> Option<Vec<Range<usize>>> {
+        let (found, spans) = match &self.kind {
+            // A query can hold for a line without anything in it to show,
+            // as "NOT retry" does.
+            Kind::Query(query, terms) => query_spans(query, terms, line),
+            _ => {
+                let spans = self.find_all(line);
+                (!spans.is_empty(), spans)
+            }
+        };
+        match (found, self.invert) {
+            (true, false) => Some(spans),
+            (false, true) => Some(Vec::new()),
+            _ => None,
+        }
+    }
+
+    // Every match in `text`, ignoring --invert-match.
+    pub fn find_all(&self, line: &str) -> Vec<Range<usize>> {
+        let accept = |span: &Range<usize>| self.is_bounded(line, span);
+        match &self.kind {
+            Kind::Literal(query) => all_spans(line, |start| {
+                let mut from = start;
+                loop {
+                    let found = from + line[from..].find(query.as_str())?;
+                    let span = found..found + query.len();
+                    if accept(&span) {
+                        return Some(span);
+                    }
+                    from = next_char(line, found)?;
+                }
+            }),
+            Kind::Literals(ac) => all_spans(line, |start| {
+                ac.find_at(line.as_bytes(), start, |range| accept(&range))
+            }),
+            Kind::Folded(ac, folding) => {
+                let units = folding.units(line);
+                let chars: Vec<char> = units.iter().map(|unit| unit.c).collect();
+                let mut from = 0;
+                all_spans(line, |start| {
+                    from = unicode::position(&units, start, from);
+                    let found = ac.find_at(&chars, from, |range| {
+                        unicode::is_whole(&units, range.clone())
+                            && accept(&unicode::span(&units, range))
+                    })?;
+                    Some(unicode::span(&units, found))
+                })
+            }
+            Kind::Fuzzy(patterns, folding, max_edits) => {
+                let units = folding.units(line);
+                let chars: Vec<char> = units.iter().map(|unit| unit.c).collect();
+                let mut from = 0;
+                all_spans(line, |start| {
+                    from = unicode::position(&units, start, from);
+                    self.find_fuzzy(line, patterns, &units, &chars, from, *max_edits)
+                })
+            }
+            Kind::Regex(regex) => all_spans(line, |start| {
+                let mut from = start;
+                loop {
+                    let span = regex.find_at(line, from)?;
+                    if accept(&span) {
+                        return Some(span);
+                    }
+                    from = next_char(line, span.start)?;
+                }
+            }),
+            Kind::Query(query, terms) => query_spans(query, terms, line).1,
+        }
+    }
+
+    // Finds the leftmost close match of any pattern, longest first, from
+    // unit `from` on. `chars` are the characters of `units`.
+    fn find_fuzzy(
+        &self,
+        line: &str,
+        patterns: &[Vec<char>],
+        units: &[Unit],
+        chars: &[char],
+        from: usize,
+        max_edits: usize,
+    ) -> Option<Range<usize>> {
+        let accept = |range: Range<usize>| {
+            unicode::is_whole(units, range.clone())
+                && self.is_bounded(line, &unicode::span(units, range))
+        };
+
+        let found = patterns
+            .iter()
+            .filter_map(|pattern| fuzzy::find(pattern, chars, from, max_edits, accept))
+            .map(|(range, _)| range)
+            .min_by_key(|range| (range.start, usize::MAX - range.end))?;
+        Some(unicode::span(units, found))
+    }
+
+    fn is_bounded(&self, line: &str, span: &Range<usize>) -> bool {
+        match self.boundary {
+            Boundary::Anywhere => true,
+            Boundary::Word => {
+                let before = line[..span.start].chars().next_back();
+                let after = line[span.end..].chars().next();
+                !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+            }
+            Boundary::Line => match self.terminator {
+                None => span.start == 0 && span.end == line.len(),
+                Some(terminator) => {
+                    let (before, after) = (&line[..span.start], &line[span.end..]);
+                    let after = after.strip_prefix('\r').filter(|_| terminator == '\n').unwrap_or(after);
+                    (before.is_empty() || before.ends_with(terminator))
+                        && (after.is_empty() || after.starts_with(terminator))
+                }
+            },
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Byte offset of the char after the one at `at`, if there is one.
+fn next_char(line: &str, at: usize) -> Option<usize> {
+    let c = line[at..].chars().next()?;
+    Some(at + c.len_utf8())
+}
+
+// Whether `query` holds for `line`, and if so the matches of the terms
+// that aren't negated, merged where they overlap.
+fn query_spans(query: &Query, terms: &[Matcher], line: &str) -> (bool, Vec<Range<usize>>) {
+    let found: Vec<Vec<Range<usize>>> = terms.iter().map(|term| term.find_all(line)).collect();
+    if !query.eval(&|term| !found[term].is_empty()) {
+        return (false, Vec::new());
+    }
+
+    let mut spans: Vec<Range<usize>> =
+        query.positive_terms().into_iter().flat_map(|term| found[term].clone()).collect();
+    spans.sort_by_key(|span| (span.start, span.end));
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    (true, merged)
+}
+
+// Collects every non-overlapping match, given a function that finds the
+// first match at or after a byte offset. Offsets only ever increase.
+fn all_spans(
+    line: &str,
+    mut find_at: impl FnMut(usize) -> Option<Range<usize>>,
+) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+
+    while let Some(span) = find_at(start) {
+        // Step past empty matches so the loop always makes progress.
+        start = match line[span.end..].chars().next() {
+            _ if span.end > span.start => span.end,
+            Some(c) => span.end + c.len_utf8(),
+            None => line.len() + 1,
+        };
+        spans.push(span);
+        if start > line.len() {
+            break;
+        }
+    }
+
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_cover_every_match() {
+        let matcher = Matcher::literal("to");
+        assert_eq!(Some(vec![0..2, 4..6, 7..9]), matcher.match_line("tomato toast"));
+        assert_eq!(None, matcher.match_line("Rust"));
+    }
+
+    #[test]
+    fn case_insensitive_spans_use_original_text() {
+        let matcher = Matcher::case_insensitive("straße");
+        assert_eq!(Some(vec![Range { start: 4, end: 11 }]), matcher.match_line("Die STRAßE ist"));
+        assert_eq!(Some(vec![Range { start: 4, end: 11 }]), matcher.match_line("Die STRASSE ist"));
+
+        // 'İ' is two bytes but folds to two chars, "i̇".
+        let matcher = Matcher::case_insensitive("x");
+        assert_eq!(Some(vec![Range { start: 3, end: 4 }]), matcher.match_line("İ-X"));
+    }
+
+    #[test]
+    fn several_patterns() {
+        let matcher = Matcher::literals(&["token", "password", "pass"]);
+        assert_eq!(Some(vec![4..12, 16..21]), matcher.match_line("bad password or token"));
+        assert_eq!(None, Matcher::literals::<&str>(&[]).match_line("anything"));
+
+        let folding = Folding { case: true, ..Default::default() };
+        let matcher = Matcher::folded(&["straße", "Key"], folding);
+        assert_eq!(Some(vec![0..3, 9..16]), matcher.match_line("KEY from STRASSE"));
+    }
+
+    #[test]
+    fn whole_words_and_lines() {
+        let matcher = Matcher::literal("to").boundary(Boundary::Word);
+        assert_eq!(Some(vec![Range { start: 8, end: 10 }]), matcher.match_line("tomato, to toast"));
+        assert_eq!(None, matcher.match_line("tomato_to"));
+
+        let matcher = Matcher::case_insensitive("rust").boundary(Boundary::Word);
+        assert_eq!(Some(vec![Range { start: 0, end: 4 }]), matcher.match_line("Rust: trust"));
+
+        let regex = Regex::new(r"\w+o").unwrap();
+        let matcher = Matcher::regex(regex).boundary(Boundary::Word);
+        assert_eq!(Some(vec![Range { start: 7, end: 12 }]), matcher.match_line("toast, tempo"));
+
+        let matcher = Matcher::literals(&["to", "toast"]).boundary(Boundary::Line);
+        assert_eq!(Some(vec![Range { start: 0, end: 5 }]), matcher.match_line("toast"));
+        assert_eq!(None, matcher.match_line("toast "));
+
+        let matcher = Matcher::literal("toast").boundary(Boundary::Line).multiline('\n');
+        assert_eq!(vec![Range { start: 3, end: 8 }], matcher.find_all("to\ntoast\r\ntoasty"));
+    }
+
+    #[test]
+    fn fuzzy_matches_typos() {
+        let folding = Folding { case: true, ..Default::default() };
+        let matcher = Matcher::fuzzy(&["recieve"], folding, 2);
+        assert_eq!(Some(vec![Range { start: 3, end: 10 }]), matcher.match_line("to RECEIVE it"));
+
+        let matcher = Matcher::fuzzy(&["lenght"], Folding::default(), 2).boundary(Boundary::Word);
+        assert_eq!(Some(vec![Range { start: 4, end: 10 }]), matcher.match_line("the length"));
+        assert_eq!(None, matcher.match_line("lengthening"));
+    }
+
+    #[test]
+    fn queries_highlight_only_positive_terms() {
+        let query = |text: &str| {
+            let mut config = Config::default();
+            config.query = Some(text.to_string());
+            config.ignore_case = true;
+            Matcher::new(&config)
+        };
+        let matcher = query("error AND (disk OR network) NOT retry").unwrap();
+        assert_eq!(Some(vec![0..5, 7..11]), matcher.match_line("ERROR: disk full"));
+        assert_eq!(None, matcher.match_line("error: disk full, retry"));
+        assert_eq!(None, matcher.match_line("network down"));
+
+        // Selected, with nothing to highlight.
+        assert_eq!(Some(vec![]), query("NOT retry").unwrap().match_line("disk full"));
+
+        assert!(matches!(query("(disk"), Err(MinigrepError::Query(_))));
+    }
+}
@@ -0,0 +1,529 @@
+// Formats search results the way grep does.
+//
+// The printer is fed every line of a file in order, together with whether
+// it matched. It keeps the last few unmatched lines around so it can print
+// them as leading context, and counts down trailing context after a match.
+// Windows that touch or overlap are merged; separate windows are divided by
+// a "--" line.
+//
+// Matching lines of binary files aren't printed; end_file prints "Binary
+// file NAME matches" instead.
+//
+// In the summary modes (-c, -l, -L) nothing is printed per line; instead
+// end_file prints the count or the file name.
+//
+// With color on, file names, line numbers and separators get their own
+// colors and every match span is highlighted, using the same ANSI escapes
+// as GNU grep's defaults.
+//
+// With --json every printed line becomes a JSON object of its own: a
+// "begin" before the first line of a file, "match" and "context" for the
+// lines, and "end" with the file's count and timing. A match that spans
+// lines (-U) is one "match" object with an "end_line_number". A binary file
+// that matches gets just a "begin" and an "end", both with "binary": true.
+// run adds a final "summary" once every file is done.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use crate::json::Object;
+use crate::stats::Stats;
+use crate::{Line, Sink};
+
+const PATH_COLOR: &str = "\x1b[35m";
+const NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Mode {
+    #[default]
+    Lines,
+    Count,
+    FilesWithMatches,
+    FilesWithoutMatch,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrintOptions {
+    pub mode: Mode,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    // Stop reading a file after this many matching lines.
+    pub max_count: Option<usize>,
+    pub color: bool,
+    pub json: bool,
+    // End printed lines with NUL instead of a newline (-z).
+    pub null_data: bool,
+}
+
+// Totals over a whole run, for the JSON summary.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Summary {
+    pub searched: usize,
+    pub with_matches: usize,
+    pub matches: usize,
+}
+
+impl Summary {
+    pub fn add(&mut self, matches: usize) {
+        self.searched += 1;
+        self.matches += matches;
+        if matches > 0 {
+            self.with_matches += 1;
+        }
+    }
+
+    // With --stats, the summary also says where the time went.
+    pub fn write_json(
+        &self,
+        out: &mut impl Write,
+        elapsed: Duration,
+        stats: Option<&Stats>,
+    ) -> io::Result<()> {
+        let mut summary = Object::new()
+            .string("type", "summary")
+            .number("searched", self.searched)
+            .number("with_matches", self.with_matches)
+            .number("matches", self.matches)
+            .number("elapsed_secs", seconds(elapsed));
+        if let Some(stats) = stats {
+            summary = summary.object("stats", stats.to_json());
+        }
+        writeln!(out, "{}", summary.render())
+    }
+}
+
+fn seconds(elapsed: Duration) -> String {
+    format!("{:.6}", elapsed.as_secs_f64())
+}
+
+// The line printed between context windows.
+pub fn separator(options: &PrintOptions) -> String {
+    paint(options.color, SEPARATOR_COLOR, "--")
+}
+
+fn paint(color: bool, code: &str, text: &str) -> String {
+    if color {
+        format!("{code}{text}{RESET}")
+    } else {
+        text.to_string()
+    }
+}
+
+pub struct Printer<W: Write> {
+    out: W,
+    options: PrintOptions,
+    name: String,
+    path: Option<String>,
+    matches: usize,
+    // Leading context: (line number, byte offset, text).
+    before: VecDeque<(usize, usize, String)>,
+    after_remaining: usize,
+    last_printed: Option<usize>,
+    printed_any: bool,
+    // Whether the current file is binary.
+    binary: bool,
+    // When the current file was started, for --json.
+    started: Instant,
+    // Lines seen over all files, for --stats.
+    lines: usize,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(out: W, options: PrintOptions) -> Printer<W> {
+        Printer {
+            out,
+            options,
+            name: String::new(),
+            path: None,
+            matches: 0,
+            before: VecDeque::new(),
+            after_remaining: 0,
+            last_printed: None,
+            printed_any: false,
+            binary: false,
+            started: Instant::now(),
+            lines: 0,
+        }
+    }
+
+    // Starts a new file. Output lines are prefixed with `name` if
+    // `with_filename` is set; -l and -L always print it.
+    pub fn begin_file(&mut self, name: &str, with_filename: bool) {
+        self.name = name.to_string();
+        self.path = with_filename.then(|| name.to_string());
+        self.matches = 0;
+        self.before.clear();
+        self.after_remaining = 0;
+        self.last_printed = None;
+        self.binary = false;
+        self.started = Instant::now();
+    }
+
+    // Marks the current file as binary: its matches are counted, but the
+    // lines themselves aren't printed.
+    pub fn binary_file(&mut self) {
+        self.binary = true;
+    }
+
+    // Finishes the current file and returns its number of matching lines.
+    pub fn end_file(&mut self) -> io::Result<usize> {
+        let color = self.options.color;
+        match self.options.mode {
+            Mode::Lines if self.binary && self.matches > 0 => {
+                if self.options.json {
+                    self.write_begin()?;
+                    self.write_end()?;
+                } else {
+                    writeln!(self.out, "Binary file {} matches", paint(color, PATH_COLOR, &self.name))?;
+                }
+            }
+            Mode::Lines if self.options.json => {
+                if self.last_printed.is_some() {
+                    self.write_end()?;
+                }
+            }
+            Mode::Lines => {}
+            Mode::Count => {
+                if let Some(path) = &self.path {
+                    let sep = paint(color, SEPARATOR_COLOR, ":");
+                    write!(self.out, "{}{sep}", paint(color, PATH_COLOR, path))?;
+                }
+                writeln!(self.out, "{}", self.matches)?;
+            }
+            Mode::FilesWithMatches if self.matches > 0 => {
+                writeln!(self.out, "{}", paint(color, PATH_COLOR, &self.name))?;
+            }
+            Mode::FilesWithoutMatch if self.matches == 0 => {
+                writeln!(self.out, "{}", paint(color, PATH_COLOR, &self.name))?;
+            }
+            Mode::FilesWithMatches | Mode::FilesWithoutMatch => {}
+        }
+        Ok(self.matches)
+    }
+
+    // True once nothing further in the current file can change the output.
+    pub fn is_done(&self) -> bool {
+        let limit_reached = self.options.max_count.is_some_and(|max| self.matches >= max);
+        match self.options.mode {
+            Mode::Lines if self.binary => limit_reached || self.matches > 0,
+            Mode::Lines => limit_reached && self.after_remaining == 0,
+            Mode::Count => limit_reached,
+            Mode::FilesWithMatches | Mode::FilesWithoutMatch => self.matches > 0,
+        }
+    }
+
+    pub fn line(&mut self, line: &Line, matched: bool) -> io::Result<()> {
+        self.lines += 1;
+        // Past the limit, matches only show up as trailing context.
+        let matched = matched && self.options.max_count.is_none_or(|max| self.matches < max);
+        if matched {
+            self.matches += 1;
+        }
+        if self.options.mode != Mode::Lines || self.binary {
+            return Ok(());
+        }
+
+        if matched {
+            while let Some((number, offset, text)) = self.before.pop_front() {
+                self.print(number, offset, &text, &[], '-')?;
+            }
+            self.print(line.number, line.offset, line.text, &line.spans, ':')?;
+            self.after_remaining = self.options.after_context;
+        } else if self.after_remaining > 0 {
+            self.print(line.number, line.offset, line.text, &[], '-')?;
+            self.after_remaining -= 1;
+        } else if self.options.before_context > 0 {
+            if self.before.len() == self.options.before_context {
+                self.before.pop_front();
+            }
+            self.before.push_back((line.number, line.offset, line.text.to_string()));
+        }
+        Ok(())
+    }
+
+    // How many lines of the current file were selected so far.
+    pub fn matches(&self) -> usize {
+        self.matches
+    }
+
+    // How many lines were looked at, over all files.
+    pub fn lines_examined(&self) -> usize {
+        self.lines
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    // Prints a selected or context line. A multiline match (-U) covers
+    // several lines at once, and each of them is printed with its own
+    // prefix.
+    fn print(
+        &mut self,
+        number: usize,
+        offset: usize,
+        text: &str,
+        spans: &[Range<usize>],
+        sep: char,
+    ) -> io::Result<()> {
+        if self.options.json {
+            return self.print_json(number, offset, text, spans, sep);
+        }
+
+        let has_context = self.options.before_context > 0 || self.options.after_context > 0;
+        let contiguous = self.last_printed.is_some_and(|last| last + 1 == number);
+        if has_context && self.printed_any && !contiguous {
+            writeln!(self.out, "{}", separator(&self.options))?;
+        }
+        self.printed_any = true;
+
+        let terminator = self.terminator();
+        let mut start = 0;
+        for (i, piece) in text.split(terminator).enumerate() {
+            let line = match terminator {
+                '\n' => piece.strip_suffix('\r').unwrap_or(piece),
+                _ => piece,
+            };
+            let end = start + line.len();
+            let spans: Vec<Range<usize>> = spans
+                .iter()
+                .map(|span| span.start.clamp(start, end) - start..span.end.clamp(start, end) - start)
+                .filter(|span| !span.is_empty())
+                .collect();
+            self.print_line(number + i, offset + start, line, &spans, sep)?;
+            start += piece.len() + terminator.len_utf8();
+        }
+        Ok(())
+    }
+
+    fn print_line(
+        &mut self,
+        number: usize,
+        offset: usize,
+        text: &str,
+        spans: &[Range<usize>],
+        sep: char,
+    ) -> io::Result<()> {
+        let color = self.options.color;
+        self.last_printed = Some(number);
+
+        let sep = paint(color, SEPARATOR_COLOR, &sep.to_string());
+        if let Some(path) = &self.path {
+            write!(self.out, "{}{sep}", paint(color, PATH_COLOR, path))?;
+        }
+        if self.options.line_number {
+            write!(self.out, "{}{sep}", paint(color, NUMBER_COLOR, &number.to_string()))?;
+        }
+        if self.options.byte_offset {
+            write!(self.out, "{}{sep}", paint(color, NUMBER_COLOR, &offset.to_string()))?;
+        }
+
+        let mut end = 0;
+        if color {
+            for span in spans.iter().filter(|span| !span.is_empty()) {
+                write!(self.out, "{}", &text[end..span.start])?;
+                write!(self.out, "{}", paint(true, MATCH_COLOR, &text[span.clone()]))?;
+                end = span.end;
+            }
+        }
+        write!(self.out, "{}{}", &text[end..], self.terminator())
+    }
+
+    // What ends lines, both in the input and in the output.
+    fn terminator(&self) -> char {
+        if self.options.null_data {
+            '\0'
+        } else {
+            '\n'
+        }
+    }
+
+    fn print_json(
+        &mut self,
+        number: usize,
+        offset: usize,
+        text: &str,
+        spans: &[Range<usize>],
+        sep: char,
+    ) -> io::Result<()> {
+        if self.last_printed.is_none() {
+            self.write_begin()?;
+        }
+        let last = number + text.matches(self.terminator()).count();
+        self.last_printed = Some(last);
+
+        let spans = spans
+            .iter()
+            .map(|span| {
+                Object::new()
+                    .number("start", span.start)
+                    .number("end", span.end)
+                    .string("text", &text[span.clone()])
+            })
+            .collect();
+        let mut line = Object::new()
+            .string("type", if sep == ':' { "match" } else { "context" })
+            .string("file", &self.name)
+            .number("line_number", number);
+        if last != number {
+            line = line.number("end_line_number", last);
+        }
+        let line = line
+            .number("byte_offset", offset)
+            .string("text", text)
+            .array("spans", spans);
+        writeln!(self.out, "{}", line.render())
+    }
+
+    fn write_begin(&mut self) -> io::Result<()> {
+        let mut begin = Object::new().string("type", "begin").string("file", &self.name);
+        if self.binary {
+            begin = begin.boolean("binary", true);
+        }
+        writeln!(self.out, "{}", begin.render())
+    }
+
+    fn write_end(&mut self) -> io::Result<()> {
+        let mut end = Object::new().string("type", "end").string("file", &self.name);
+        if self.binary {
+            end = end.boolean("binary", true);
+        }
+        let end = end
+            .number("matches", self.matches)
+            .number("elapsed_secs", seconds(self.started.elapsed()));
+        writeln!(self.out, "{}", end.render())
+    }
+}
+
+impl<W: Write> Sink for Printer<W> {
+    fn matched(&mut self, line: &Line) -> io::Result<bool> {
+        self.line(line, true)?;
+        Ok(!self.is_done())
+    }
+
+    fn context(&mut self, line: &Line) -> io::Result<bool> {
+        self.line(line, false)?;
+        Ok(!self.is_done())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines;
+
+    fn print(options: PrintOptions, matches: &[usize]) -> String {
+        let contents = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n";
+        let mut printer = Printer::new(Vec::new(), options);
+        printer.begin_file("numbers.txt", false);
+        for line in lines(contents) {
+            if printer.is_done() {
+                break;
+            }
+            printer.line(&line, matches.contains(&line.number)).unwrap();
+        }
+        printer.end_file().unwrap();
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn line_numbers_and_offsets() {
+        let options = PrintOptions { line_number: true, byte_offset: true, ..Default::default() };
+        assert_eq!("2:4:two\n5:19:five\n", print(options, &[2, 5]));
+    }
+
+    #[test]
+    fn context_windows_merge() {
+        let options = PrintOptions { before_context: 1, after_context: 1, ..Default::default() };
+        assert_eq!("one\ntwo\nthree\nfour\n", print(options, &[2, 3]));
+        assert_eq!("one\ntwo\nthree\n--\nfive\nsix\nseven\n", print(options, &[2, 6]));
+
+        let options = PrintOptions { line_number: true, after_context: 1, ..Default::default() };
+        assert_eq!("2:two\n3-three\n4:four\n5-five\n", print(options, &[2, 4]));
+    }
+
+    #[test]
+    fn color_highlights_spans() {
+        let options = PrintOptions { line_number: true, color: true, ..Default::default() };
+        let mut printer = Printer::new(Vec::new(), options);
+        printer.begin_file("poem.txt", true);
+        let line = Line::new(3, 0, "día día", vec![1..4, 6..9]);
+        printer.line(&line, true).unwrap();
+
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m3\x1b[0m\x1b[36m:\x1b[0m\
+             d\x1b[1;31mía\x1b[0m d\x1b[1;31mía\x1b[0m\n",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn max_count_keeps_trailing_context() {
+        let options = PrintOptions { after_context: 2, max_count: Some(1), ..Default::default() };
+        assert_eq!("two\nthree\nfour\n", print(options, &[2, 3, 5]));
+    }
+
+    #[test]
+    fn json_records() {
+        let options = PrintOptions { json: true, after_context: 1, ..Default::default() };
+        let mut printer = Printer::new(Vec::new(), options);
+        printer.begin_file("poem.txt", false);
+        for mut line in lines("día\nnoche\n") {
+            let matched = line.number == 1;
+            if matched {
+                line.spans = vec![Range { start: 1, end: 3 }];
+            }
+            printer.line(&line, matched).unwrap();
+        }
+        printer.end_file().unwrap();
+
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        let records: Vec<&str> = output.lines().collect();
+        assert_eq!(4, records.len());
+        assert_eq!(r#"{"type":"begin","file":"poem.txt"}"#, records[0]);
+        assert_eq!(
+            r#"{"type":"match","file":"poem.txt","line_number":1,"byte_offset":0,"text":"día","spans":[{"start":1,"end":3,"text":"í"}]}"#,
+            records[1]
+        );
+        assert_eq!(
+            r#"{"type":"context","file":"poem.txt","line_number":2,"byte_offset":5,"text":"noche","spans":[]}"#,
+            records[2]
+        );
+        assert!(records[3].starts_with(r#"{"type":"end","file":"poem.txt","matches":1,"elapsed_secs":"#));
+
+        let mut printer = Printer::new(Vec::new(), options);
+        printer.begin_file("image.bin", false);
+        printer.binary_file();
+        for line in lines("día\n") {
+            printer.line(&line, true).unwrap();
+        }
+        printer.end_file().unwrap();
+
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        let records: Vec<&str> = output.lines().collect();
+        assert_eq!(2, records.len());
+        assert_eq!(r#"{"type":"begin","file":"image.bin","binary":true}"#, records[0]);
+        assert!(records[1].starts_with(r#"{"type":"end","file":"image.bin","binary":true,"matches":1,"#));
+    }
+
+    #[test]
+    fn summary_modes() {
+        let options = PrintOptions { mode: Mode::Count, ..Default::default() };
+        assert_eq!("3\n", print(options, &[2, 3, 5]));
+
+        let options = PrintOptions { mode: Mode::FilesWithMatches, ..Default::default() };
+        assert_eq!("numbers.txt\n", print(options, &[5]));
+        assert_eq!("", print(options, &[]));
+
+        let options = PrintOptions { mode: Mode::FilesWithoutMatch, ..Default::default() };
+        assert_eq!("", print(options, &[5]));
+        assert_eq!("numbers.txt\n", print(options, &[]));
+    }
+}
@@ -0,0 +1,232 @@
+// A minimal worker pool for searching many files at once.
+//
+// Workers take items off a shared counter, so a slow file doesn't hold up
+// the others. Results are handed back to the calling thread in the same
+// order as the items, which keeps the output deterministic no matter how
+// the work was scheduled.
+//
+// Memory stays bounded however large the output or slow the first file:
+// workers only run WINDOW items per thread ahead of the one being handed
+// back, and what an item writes travels in chunks over a channel that
+// holds at most CHUNKS_PER_ITEM of them. The item at the head of the order
+// is streamed as it is written; the others wait once their channel fills.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+// Items that may be started, per thread, before the head of the order is
+// done.
+const WINDOW: usize = 2;
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHUNKS_PER_ITEM: usize = 16;
+
+// What an item produced, in the order it was produced.
+pub enum Event<R> {
+    Output(Vec<u8>),
+    Done(R),
+}
+
+// Where an item writes its output, to be handed back in chunks.
+pub struct Output<R> {
+    sender: SyncSender<Event<R>>,
+    buf: Vec<u8>,
+}
+
+impl<R> Output<R> {
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        self.sender
+            .send(Event::Output(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "output is no longer read"))
+    }
+}
+
+impl<R> Write for Output<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+// Runs `work` on every item using up to `threads` threads and calls `each`
+// with the results in item order. Stops early if `each` returns an error.
+pub fn for_each_ordered<T, R, E, F, G>(
+    items: &[T],
+    threads: usize,
+    work: F,
+    mut each: G,
+) -> Result<(), E>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    G: FnMut(&T, R) -> Result<(), E>,
+{
+    for_each_streamed(items, threads, |item, _| work(item), |item, event| match event {
+        Event::Output(_) => Ok(()),
+        Event::Done(result) => each(item, result),
+    })
+}
+
+// Like for_each_ordered, except that `work` also writes output, which
+// `each` is given as it arrives, before the item's result.
+pub fn for_each_streamed<T, R, E, F, G>(
+    items: &[T],
+    threads: usize,
+    work: F,
+    mut each: G,
+) -> Result<(), E>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T, &mut Output<R>) -> R + Sync,
+    G: FnMut(&T, Event<R>) -> Result<(), E>,
+{
+    let threads = threads.clamp(1, items.len().max(1));
+    let window = threads * WINDOW;
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    // The item being handed back, which workers mustn't get too far ahead of.
+    let head = (Mutex::new(0), Condvar::new());
+    let (sender, receiver) = mpsc::channel::<(usize, Receiver<Event<R>>)>();
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (next, stop, head, work) = (&next, &stop, &head, &work);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= items.len() {
+                        break;
+                    }
+                    let (lock, moved) = head;
+                    let mut wanted = lock.lock().unwrap();
+                    while i >= *wanted + window && !stop.load(Ordering::Relaxed) {
+                        wanted = moved.wait(wanted).unwrap();
+                    }
+                    drop(wanted);
+
+                    let (events, received) = mpsc::sync_channel(CHUNKS_PER_ITEM);
+                    if sender.send((i, received)).is_err() {
+                        break;
+                    }
+                    let mut output = Output { sender: events, buf: Vec::with_capacity(CHUNK_SIZE) };
+                    let result = work(&items[i], &mut output);
+                    // Sends fail only once `each` has stopped early.
+                    let _ = output.send();
+                    let Output { sender: events, .. } = output;
+                    let _ = events.send(Event::Done(result));
+                }
+            });
+        }
+        drop(sender);
+
+        // Items that were started before the ones ahead of them finished.
+        let mut pending = BTreeMap::new();
+        let mut wanted = 0;
+        let stopped = |err| {
+            stop.store(true, Ordering::Relaxed);
+            head.1.notify_all();
+            Err(err)
+        };
+
+        for (i, events) in receiver {
+            pending.insert(i, events);
+            while let Some(events) = pending.remove(&wanted) {
+                for event in events {
+                    if let Err(err) = each(&items[wanted], event) {
+                        return stopped(err);
+                    }
+                }
+                wanted += 1;
+                *head.0.lock().unwrap() = wanted;
+                head.1.notify_all();
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn results_come_back_in_order() {
+        let items: Vec<u64> = (0..20).collect();
+        let mut seen = Vec::new();
+
+        for_each_ordered(
+            &items,
+            4,
+            |&item| {
+                // Make early items finish last.
+                thread::sleep(Duration::from_millis(20 - item));
+                item * 10
+            },
+            |&item, result| {
+                assert_eq!(item * 10, result);
+                seen.push(item);
+                Ok::<(), ()>(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(items, seen);
+    }
+
+    #[test]
+    fn output_streams_in_order() {
+        let items: Vec<usize> = (0..8).collect();
+        let mut output: Vec<u8> = Vec::new();
+        let mut done = Vec::new();
+
+        for_each_streamed(
+            &items,
+            3,
+            |&item, out| {
+                // Much more than the channel holds, so later items wait.
+                for _ in 0..CHUNK_SIZE * CHUNKS_PER_ITEM / 4 {
+                    out.write_all(&[b'a' + item as u8; 8]).unwrap();
+                }
+                item
+            },
+            |&item, event| {
+                match event {
+                    Event::Output(chunk) => output.extend(chunk),
+                    Event::Done(result) => {
+                        assert_eq!(item, result);
+                        done.push(item);
+                    }
+                }
+                Ok::<(), ()>(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(items, done);
+        let per_item = CHUNK_SIZE * CHUNKS_PER_ITEM * 2;
+        assert_eq!(items.len() * per_item, output.len());
+        for (bytes, &item) in output.chunks(per_item).zip(&items) {
+            assert!(bytes.iter().all(|&b| b == b'a' + item as u8));
+        }
+    }
+}
@@ -0,0 +1,282 @@
+// Boolean queries over whole lines (--query).
+//
+//   error AND (disk OR network) NOT retry
+//
+// A term is a word, or a phrase in double quotes, and matches a line that
+// contains it the way a plain QUERY would. Terms combine with AND, OR and
+// NOT, which have to be written in capitals; in lowercase they are just
+// words. Terms next to each other must all match, so "disk full" is the
+// same as "disk AND full", and "a NOT b" is "a AND NOT b". NOT binds
+// tightest and OR loosest; parentheses group.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    // An index into the list of terms returned alongside the query.
+    Term(usize),
+    Not(Box<Query>),
+    All(Vec<Query>),
+    Any(Vec<Query>),
+}
+
+impl Query {
+    // Whether a line matches, given which of the terms it contains.
+    pub fn eval(&self, contains: &impl Fn(usize) -> bool) -> bool {
+        match self {
+            Query::Term(term) => contains(*term),
+            Query::Not(query) => !query.eval(contains),
+            Query::All(queries) => queries.iter().all(|query| query.eval(contains)),
+            Query::Any(queries) => queries.iter().any(|query| query.eval(contains)),
+        }
+    }
+
+    // The terms not under a NOT, whose matches are worth highlighting.
+    pub fn positive_terms(&self) -> Vec<usize> {
+        match self {
+            Query::Term(term) => vec![*term],
+            Query::Not(_) => Vec::new(),
+            Query::All(queries) | Query::Any(queries) => {
+                queries.iter().flat_map(Query::positive_terms).collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub query: String,
+    // 1-based, in chars.
+    pub column: usize,
+    pub message: String,
+}
+
+// Points at the problem under the query.
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "invalid query: {} at column {}", self.message, self.column)?;
+        writeln!(f, "  {}", self.query)?;
+        write!(f, "  {}^", " ".repeat(self.column - 1))
+    }
+}
+
+impl Error for QueryError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+// Parses `text` into a query and the terms it refers to.
+pub fn parse(text: &str) -> Result<(Query, Vec<String>), QueryError> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser { text, tokens, next: 0, terms: Vec::new() };
+    let query = parser.any()?;
+    if let Some((column, _)) = parser.tokens.get(parser.next) {
+        // Only a stray ')' stops the top level early.
+        return Err(parser.error(*column, "unmatched ')'"));
+    }
+    Ok((query, parser.terms))
+}
+
+// Splits `text` into tokens, each with the column it starts at.
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().enumerate().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let column = i + 1;
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '"' => {
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => phrase.push(c),
+                            None => break,
+                        },
+                        Some((_, c)) => phrase.push(c),
+                        None => {
+                            return Err(QueryError {
+                                query: text.to_string(),
+                                column,
+                                message: "unclosed quote".to_string(),
+                            })
+                        }
+                    }
+                }
+                Token::Word(phrase)
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some((_, c)) =
+                    chars.next_if(|(_, c)| !c.is_whitespace() && !matches!(c, '(' | ')' | '"'))
+                {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        tokens.push((column, token));
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'t> {
+    text: &'t str,
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    terms: Vec<String>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    // The column of the next token, or just past the end.
+    fn column(&self) -> usize {
+        match self.tokens.get(self.next) {
+            Some((column, _)) => *column,
+            None => self.text.chars().count() + 1,
+        }
+    }
+
+    fn error(&self, column: usize, message: &str) -> QueryError {
+        QueryError { query: self.text.to_string(), column, message: message.to_string() }
+    }
+
+    // any := all ("OR" all)*
+    fn any(&mut self) -> Result<Query, QueryError> {
+        let mut queries = vec![self.all()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            queries.push(self.all()?);
+        }
+        Ok(if queries.len() == 1 { queries.remove(0) } else { Query::Any(queries) })
+    }
+
+    // all := not (["AND"] not)*
+    fn all(&mut self) -> Result<Query, QueryError> {
+        let mut queries = vec![self.not()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.next += 1,
+                Some(Token::Not | Token::Open | Token::Word(_)) => {}
+                _ => break,
+            }
+            queries.push(self.not()?);
+        }
+        Ok(if queries.len() == 1 { queries.remove(0) } else { Query::All(queries) })
+    }
+
+    // not := "NOT" not | term | "(" any ")"
+    fn not(&mut self) -> Result<Query, QueryError> {
+        let column = self.column();
+        let Some((_, token)) = self.tokens.get(self.next).cloned() else {
+            let message = match self.next.checked_sub(1).map(|last| &self.tokens[last].1) {
+                None => "empty query",
+                Some(Token::Open) => "expected a term after '('",
+                Some(_) => "expected a term after the operator",
+            };
+            return Err(self.error(column, message));
+        };
+        self.next += 1;
+
+        match token {
+            Token::Not => Ok(Query::Not(Box::new(self.not()?))),
+            Token::Word(word) => {
+                // Repeated terms are only searched for once.
+                let term = match self.terms.iter().position(|term| *term == word) {
+                    Some(term) => term,
+                    None => {
+                        self.terms.push(word);
+                        self.terms.len() - 1
+                    }
+                };
+                Ok(Query::Term(term))
+            }
+            Token::Open => {
+                let query = self.any()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(self.error(column, "unclosed '('"));
+                }
+                self.next += 1;
+                Ok(query)
+            }
+            Token::And | Token::Or => Err(self.error(column, "expected a term before the operator")),
+            Token::Close => Err(self.error(column, "expected a term before ')'")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(query: &str, line: &str) -> bool {
+        let (query, terms) = parse(query).unwrap();
+        query.eval(&|term| line.contains(terms[term].as_str()))
+    }
+
+    #[test]
+    fn parses_and_evaluates() {
+        let (query, terms) = parse("error AND (disk OR network) NOT retry").unwrap();
+        assert_eq!(vec!["error", "disk", "network", "retry"], terms);
+        assert_eq!(
+            Query::All(vec![
+                Query::Term(0),
+                Query::Any(vec![Query::Term(1), Query::Term(2)]),
+                Query::Not(Box::new(Query::Term(3))),
+            ]),
+            query
+        );
+        assert_eq!(vec![0, 1, 2], query.positive_terms());
+
+        let query = "error AND (disk OR network) NOT retry";
+        assert!(eval(query, "error: disk full"));
+        assert!(!eval(query, "error: disk full, retry in 5s"));
+        assert!(!eval(query, "warning: network down"));
+
+        assert!(eval("\"disk full\" OR and", "the disk full"));
+        assert!(eval("\"disk full\" OR and", "this and that"));
+        assert!(!eval("\"disk full\" OR and", "full disk"));
+        assert!(eval("NOT NOT a b", "a b"));
+        assert!(eval("a OR b c", "a"));
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let error = |query: &str| {
+            let err = parse(query).err().unwrap();
+            (err.column, err.message)
+        };
+        assert_eq!((1, "empty query".to_string()), error(""));
+        assert_eq!((10, "expected a term after the operator".to_string()), error("error AND"));
+        assert_eq!((7, "unclosed '('".to_string()), error("error (disk OR net"));
+        assert_eq!((6, "unmatched ')'".to_string()), error("disk ) net"));
+        assert_eq!((1, "expected a term before the operator".to_string()), error("OR disk"));
+        assert_eq!((7, "unclosed quote".to_string()), error("error \"disk"));
+
+        assert_eq!(
+            "invalid query: unclosed '(' at column 7\n  error (disk\n        ^",
+            parse("error (disk").err().unwrap().to_string()
+        );
+    }
+}
@@ -0,0 +1,883 @@
+// A small, self-contained regular expression engine for minigrep.
+//
+// Patterns are parsed into a syntax tree, compiled into a list of
+// instructions and executed with a Pike VM (a breadth-first simulation of
+// the compiled automaton). The VM never backtracks, so matching takes time
+// proportional to pattern size times text size and memory proportional to
+// the pattern size only.
+//
+// Supported syntax:
+//   literals        a  \.  \\  \n  \t  \r  \xHH
+//   any character   .            (does not match '\n' unless (?s) is set)
+//   classes         [abc]  [^a-z]  [\d_]  \d \D \w \W \s \S
+//   anchors         ^  $  \A  \z  \b  \B
+//   groups          (...)  (?:...)  (?flags)  (?flags:...)   flags: i m s
+//   alternation     a|b
+//   quantifiers     *  +  ?  {n}  {n,}  {n,m}   (append ? to make lazy)
+//
+// Ignoring case, characters are compared one to one, except that a
+// character of the pattern whose full case folding is several characters
+// also matches those: "ß" matches "SS", but "ss" doesn't match "ß".
+
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use crate::unicode::Folding;
+
+// Upper bound for the counts in {n,m}.
+const MAX_REPEAT: u32 = 1000;
+
+// Upper bound for the number of instructions. Every count of a repetition
+// gets its own copy of what it repeats, so nested counts multiply, and this
+// is what keeps a pattern like ((a{1000}){1000}){1000} from blowing up the
+// program.
+const MAX_PROGRAM: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError {
+    pub message: &'static str,
+    pub position: usize,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "regex parse error at position {}: {}", self.position, self.message)
+    }
+}
+
+impl Error for RegexError {}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: String,
+    prog: Vec<Inst>,
+    // Number of capture groups, including the implicit group 0.
+    groups: usize,
+    // True when every match has to start at the beginning of the text.
+    anchored: bool,
+}
+
+pub struct RegexBuilder {
+    pattern: String,
+    flags: Flags,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> RegexBuilder {
+        RegexBuilder { pattern: pattern.to_string(), flags: Flags::default() }
+    }
+
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.flags.ignore_case = yes;
+        self
+    }
+
+    // '^' and '$' also match at the start and end of every line.
+    pub fn multi_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.flags.multi_line = yes;
+        self
+    }
+
+    // '.' also matches '\n'.
+    pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.flags.dot_all = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: self.pattern.chars().collect(),
+            pos: 0,
+            groups: 1,
+            flags: self.flags,
+        };
+        let ast = parser.parse()?;
+        if program_size(&ast) > MAX_PROGRAM {
+            let message = "pattern too large once repetitions are expanded";
+            return Err(RegexError { message, position: 0 });
+        }
+
+        let mut prog = vec![Inst::Save(0)];
+        compile(&ast, &mut prog);
+        prog.push(Inst::Save(1));
+        prog.push(Inst::Match);
+
+        Ok(Regex {
+            pattern: self.pattern.clone(),
+            prog,
+            groups: parser.groups,
+            anchored: is_anchored(&ast),
+        })
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find_at(text, 0).is_some()
+    }
+
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        self.find_at(text, 0)
+    }
+
+    // Finds the leftmost match that starts at or after byte offset `start`.
+    // Anchors and word boundaries still look at the text before `start`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<Range<usize>> {
+        let slots = self.exec(text, start)?;
+        Some(slots[0]?..slots[1]?)
+    }
+
+    // Like find_at, but returns the range of every capture group, with the
+    // whole match as group 0. Groups that took no part in the match are None.
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Vec<Option<Range<usize>>>> {
+        let slots = self.exec(text, start)?;
+        Some(slots.chunks(2).map(|pair| Some(pair[0]?..pair[1]?)).collect())
+    }
+
+    fn exec(&self, text: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        let mut clist = Threads::new(self.prog.len());
+        let mut nlist = Threads::new(self.prog.len());
+        let mut matched = None;
+        let mut pos = start;
+
+        loop {
+            let may_start = matched.is_none() && (!self.anchored || pos == start);
+            if may_start {
+                let slots = vec![None; self.groups * 2];
+                self.add_thread(&mut clist, 0, pos, text, slots);
+            } else if clist.threads.is_empty() {
+                break;
+            }
+
+            let next = text[pos..].chars().next();
+            let width = next.map_or(0, char::len_utf8);
+
+            for (pc, slots) in clist.threads.drain(..) {
+                let consumed = match (&self.prog[pc], next) {
+                    (Inst::Match, _) => {
+                        // Threads after this one have lower priority.
+                        matched = Some(slots);
+                        break;
+                    }
+                    (Inst::Char(want, ignore_case), Some(c)) => chars_eq(*want, c, *ignore_case),
+                    (Inst::Any(dot_all), Some(c)) => *dot_all || c != '\n',
+                    (Inst::Class(class), Some(c)) => class.matches(c),
+                    _ => false,
+                };
+                if consumed {
+                    self.add_thread(&mut nlist, pc + 1, pos + width, text, slots);
+                }
+            }
+
+            if next.is_none() {
+                break;
+            }
+            pos += width;
+            clist.clear();
+            std::mem::swap(&mut clist, &mut nlist);
+        }
+
+        matched
+    }
+
+    // Follows every non-consuming instruction reachable from `pc`, adding
+    // the consuming ones to `list` in priority order.
+    fn add_thread(
+        &self,
+        list: &mut Threads,
+        pc: usize,
+        pos: usize,
+        text: &str,
+        slots: Vec<Option<usize>>,
+    ) {
+        let mut stack = vec![(pc, slots)];
+
+        while let Some((pc, mut slots)) = stack.pop() {
+            if list.seen[pc] {
+                continue;
+            }
+            list.seen[pc] = true;
+
+            match &self.prog[pc] {
+                Inst::Jmp(to) => stack.push((*to, slots)),
+                Inst::Split(first, second) => {
+                    stack.push((*second, slots.clone()));
+                    stack.push((*first, slots));
+                }
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(pos);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Look(look) => {
+                    if look.holds(text, pos) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => list.threads.push((pc, slots)),
+            }
+        }
+    }
+}
+
+struct Threads {
+    seen: Vec<bool>,
+    threads: Vec<(usize, Vec<Option<usize>>)>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Threads {
+        Threads { seen: vec![false; size], threads: Vec::new() }
+    }
+
+    fn clear(&mut self) {
+        self.seen.iter_mut().for_each(|seen| *seen = false);
+        self.threads.clear();
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char, bool),
+    Any(bool),
+    Class(Class),
+    Look(Look),
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Look {
+    StartText,
+    EndText,
+    StartLine,
+    EndLine,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Look {
+    fn holds(self, text: &str, pos: usize) -> bool {
+        let before = text[..pos].chars().next_back();
+        let after = text[pos..].chars().next();
+
+        match self {
+            Look::StartText => pos == 0,
+            Look::EndText => pos == text.len(),
+            Look::StartLine => before.is_none_or(|c| c == '\n'),
+            // Before "\r\n" as well, so that CRLF lines end where they do
+            // when searched one at a time without their line endings.
+            Look::EndLine => match after {
+                None => true,
+                Some('\r') => text[pos + 1..].starts_with('\n'),
+                Some('\n') => before != Some('\r'),
+                Some(_) => false,
+            },
+            Look::WordBoundary => before.is_some_and(is_word) != after.is_some_and(is_word),
+            Look::NotWordBoundary => before.is_some_and(is_word) == after.is_some_and(is_word),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+    ignore_case: bool,
+}
+
+impl Class {
+    fn perl(kind: Perl, negated: bool) -> Class {
+        Class { items: vec![ClassItem::Perl(kind, negated)], negated: false, ignore_case: false }
+    }
+
+    fn matches(&self, c: char) -> bool {
+        let found = if self.ignore_case {
+            self.contains(c) || self.contains(fold(c)) || self.contains(unfold(c))
+        } else {
+            self.contains(c)
+        };
+        found != self.negated
+    }
+
+    fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|item| match item {
+            ClassItem::Range(lo, hi) => *lo <= c && c <= *hi,
+            ClassItem::Perl(kind, negated) => kind.matches(c) != *negated,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Flags {
+    ignore_case: bool,
+    multi_line: bool,
+    dot_all: bool,
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char, bool),
+    Any(bool),
+    Class(Class),
+    Look(Look),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat { node: Box<Node>, min: u32, max: Option<u32>, greedy: bool },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+    flags: Flags,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Result<Node, RegexError> {
+        let node = self.parse_alternation()?;
+        if self.pos < self.chars.len() {
+            // The only way to stop early is an unmatched ')'.
+            return Err(self.error("unopened group"));
+        }
+        Ok(node)
+    }
+
+    fn error(&self, message: &'static str) -> RegexError {
+        RegexError { message, position: self.pos }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn next(&mut self) -> Result<char, RegexError> {
+        let c = self.peek().ok_or_else(|| self.error("unexpected end of pattern"))?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap())
+        } else {
+            Ok(Node::Alternate(branches))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = match self.parse_atom()? {
+                Some(atom) => atom,
+                // A bare flag group like (?i) only changes the flags.
+                None => continue,
+            };
+            nodes.push(self.parse_repeat(atom)?);
+        }
+
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.pop().unwrap()),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Option<Node>, RegexError> {
+        let flags = self.flags;
+        let node = match self.next()? {
+            '.' => Node::Any(flags.dot_all),
+            '^' if flags.multi_line => Node::Look(Look::StartLine),
+            '^' => Node::Look(Look::StartText),
+            '$' if flags.multi_line => Node::Look(Look::EndLine),
+            '$' => Node::Look(Look::EndText),
+            '[' => Node::Class(self.parse_class()?),
+            '(' => return self.parse_group(),
+            '\\' => self.parse_escape()?,
+            '*' | '+' | '?' => return Err(self.error("repetition operator missing expression")),
+            c => literal(c, flags.ignore_case),
+        };
+        Ok(Some(node))
+    }
+
+    fn parse_group(&mut self) -> Result<Option<Node>, RegexError> {
+        let outer = self.flags;
+        let mut index = None;
+
+        if self.eat('?') {
+            loop {
+                match self.next()? {
+                    'i' => self.flags.ignore_case = true,
+                    'm' => self.flags.multi_line = true,
+                    's' => self.flags.dot_all = true,
+                    // (?flags) applies to the rest of the enclosing group.
+                    ')' => return Ok(None),
+                    ':' => break,
+                    _ => return Err(self.error("unrecognized group flag")),
+                }
+            }
+        } else {
+            index = Some(self.groups);
+            self.groups += 1;
+        }
+
+        let node = self.parse_alternation()?;
+        if !self.eat(')') {
+            return Err(self.error("unclosed group"));
+        }
+        self.flags = outer;
+
+        Ok(Some(Node::Group(Box::new(node), index)))
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, RegexError> {
+        let node = match self.peek() {
+            Some('b') => Node::Look(Look::WordBoundary),
+            Some('B') => Node::Look(Look::NotWordBoundary),
+            Some('A') => Node::Look(Look::StartText),
+            Some('z') => Node::Look(Look::EndText),
+            _ => {
+                return Ok(match self.parse_class_escape()? {
+                    ClassItem::Range(c, _) => literal(c, self.flags.ignore_case),
+                    ClassItem::Perl(kind, negated) => Node::Class(Class::perl(kind, negated)),
+                });
+            }
+        };
+        self.pos += 1;
+        Ok(node)
+    }
+
+    // Parses the part of an escape after the '\' that is valid both inside
+    // and outside of a character class.
+    fn parse_class_escape(&mut self) -> Result<ClassItem, RegexError> {
+        let c = match self.next()? {
+            'd' => return Ok(ClassItem::Perl(Perl::Digit, false)),
+            'D' => return Ok(ClassItem::Perl(Perl::Digit, true)),
+            'w' => return Ok(ClassItem::Perl(Perl::Word, false)),
+            'W' => return Ok(ClassItem::Perl(Perl::Word, true)),
+            's' => return Ok(ClassItem::Perl(Perl::Space, false)),
+            'S' => return Ok(ClassItem::Perl(Perl::Space, true)),
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'f' => '\x0c',
+            'v' => '\x0b',
+            '0' => '\0',
+            'x' => self.parse_hex()?,
+            c if c.is_alphanumeric() => {
+                self.pos -= 1;
+                return Err(self.error("unrecognized escape sequence"));
+            }
+            c => c,
+        };
+        Ok(ClassItem::Range(c, c))
+    }
+
+    fn parse_hex(&mut self) -> Result<char, RegexError> {
+        let mut value = 0;
+        for _ in 0..2 {
+            let digit = self.next()?.to_digit(16).ok_or_else(|| self.error("invalid hex escape"))?;
+            value = value * 16 + digit;
+        }
+        Ok(char::from_u32(value).unwrap())
+    }
+
+    fn parse_class(&mut self) -> Result<Class, RegexError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+
+        // A ']' right after the opening bracket is a literal.
+        if self.eat(']') {
+            items.push(ClassItem::Range(']', ']'));
+        }
+
+        loop {
+            let item = match self.next()? {
+                ']' => break,
+                '\\' => self.parse_class_escape()?,
+                c => ClassItem::Range(c, c),
+            };
+
+            let lo = match item {
+                ClassItem::Range(lo, _) => lo,
+                perl => {
+                    items.push(perl);
+                    continue;
+                }
+            };
+
+            // A '-' just before the closing bracket is a literal.
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                self.pos += 1;
+                let hi = match self.next()? {
+                    '\\' => match self.parse_class_escape()? {
+                        ClassItem::Range(hi, _) => hi,
+                        ClassItem::Perl(..) => return Err(self.error("invalid class range")),
+                    },
+                    c => c,
+                };
+                if hi < lo {
+                    return Err(self.error("invalid class range"));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+
+        Ok(Class { items, negated, ignore_case: self.flags.ignore_case })
+    }
+
+    fn parse_repeat(&mut self, mut node: Node) -> Result<Node, RegexError> {
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.parse_counts()? {
+                    Some(counts) => counts,
+                    // Not a valid counted repetition, so '{' is a literal.
+                    None => return Ok(node),
+                },
+                _ => return Ok(node),
+            };
+            if start == self.pos {
+                self.pos += 1;
+            }
+
+            if matches!(node, Node::Look(_) | Node::Empty) {
+                self.pos = start;
+                return Err(self.error("repetition operator missing expression"));
+            }
+
+            let greedy = !self.eat('?');
+            node = Node::Repeat { node: Box::new(node), min, max, greedy };
+        }
+    }
+
+    fn parse_counts(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let min = self.parse_number();
+        let max = if self.eat(',') { self.parse_number() } else { min };
+        let closed = self.eat('}');
+
+        let min = match (min, closed) {
+            (Some(min), true) => min,
+            _ => {
+                self.pos = start;
+                return Ok(None);
+            }
+        };
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            return Err(self.error("repetition count too large"));
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err(self.error("invalid repetition range"));
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok()
+    }
+}
+
+// A literal character. Ignoring case, one whose full case folding is
+// several characters also matches those, so "ß" matches "SS".
+fn literal(c: char, ignore_case: bool) -> Node {
+    let folded = Folding { case: true, ..Default::default() }.chars(c.encode_utf8(&mut [0; 4]));
+    if !ignore_case || folded.len() < 2 {
+        return Node::Char(c, ignore_case);
+    }
+    let expansion = Node::Concat(folded.into_iter().map(|f| Node::Char(f, true)).collect());
+    Node::Group(Box::new(Node::Alternate(vec![Node::Char(c, true), expansion])), None)
+}
+
+fn compile(node: &Node, prog: &mut Vec<Inst>) {
+    match node {
+        Node::Empty => {}
+        Node::Char(c, ignore_case) => prog.push(Inst::Char(*c, *ignore_case)),
+        Node::Any(dot_all) => prog.push(Inst::Any(*dot_all)),
+        Node::Class(class) => prog.push(Inst::Class(class.clone())),
+        Node::Look(look) => prog.push(Inst::Look(*look)),
+        Node::Group(node, None) => compile(node, prog),
+        Node::Group(node, Some(index)) => {
+            prog.push(Inst::Save(index * 2));
+            compile(node, prog);
+            prog.push(Inst::Save(index * 2 + 1));
+        }
+        Node::Concat(nodes) => nodes.iter().for_each(|node| compile(node, prog)),
+        Node::Alternate(branches) => {
+            // Each branch but the last is preceded by a split to the next
+            // branch and followed by a jump past the end.
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 < branches.len() {
+                    let split = prog.len();
+                    prog.push(Inst::Split(split + 1, 0));
+                    compile(branch, prog);
+                    jumps.push(prog.len());
+                    prog.push(Inst::Jmp(0));
+                    let next = prog.len();
+                    prog[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(branch, prog);
+                }
+            }
+            let end = prog.len();
+            for jump in jumps {
+                prog[jump] = Inst::Jmp(end);
+            }
+        }
+        Node::Repeat { node, min, max, greedy } => {
+            for _ in 0..*min {
+                compile(node, prog);
+            }
+            match max {
+                None => {
+                    let split = prog.len();
+                    prog.push(Inst::Split(0, 0));
+                    compile(node, prog);
+                    prog.push(Inst::Jmp(split));
+                    prog[split] = split_inst(split + 1, prog.len(), *greedy);
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(prog.len());
+                        prog.push(Inst::Split(0, 0));
+                        compile(node, prog);
+                    }
+                    let end = prog.len();
+                    for split in splits {
+                        prog[split] = split_inst(split + 1, end, *greedy);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The number of instructions compile() emits for `node`, saturating
+// instead of overflowing.
+fn program_size(node: &Node) -> usize {
+    match node {
+        Node::Empty => 0,
+        Node::Char(..) | Node::Any(_) | Node::Class(_) | Node::Look(_) => 1,
+        Node::Group(node, None) => program_size(node),
+        Node::Group(node, Some(_)) => program_size(node).saturating_add(2),
+        Node::Concat(nodes) => nodes.iter().map(program_size).fold(0, usize::saturating_add),
+        Node::Alternate(branches) => {
+            let splits_and_jumps = 2 * branches.len().saturating_sub(1);
+            branches.iter().map(program_size).fold(splits_and_jumps, usize::saturating_add)
+        }
+        Node::Repeat { node, min, max, .. } => {
+            let body = program_size(node);
+            let optional = match max {
+                // A split and a jump around one more copy.
+                None => body.saturating_add(2),
+                // A split before each optional copy.
+                Some(max) => body.saturating_add(1).saturating_mul((max - min) as usize),
+            };
+            body.saturating_mul(*min as usize).saturating_add(optional)
+        }
+    }
+}
+
+fn split_inst(body: usize, exit: usize, greedy: bool) -> Inst {
+    if greedy {
+        Inst::Split(body, exit)
+    } else {
+        Inst::Split(exit, body)
+    }
+}
+
+fn is_anchored(node: &Node) -> bool {
+    match node {
+        Node::Look(Look::StartText) => true,
+        Node::Group(node, _) => is_anchored(node),
+        Node::Concat(nodes) => nodes.first().is_some_and(is_anchored),
+        Node::Alternate(branches) => branches.iter().all(is_anchored),
+        _ => false,
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn chars_eq(want: char, c: char, ignore_case: bool) -> bool {
+    want == c || (ignore_case && fold(want) == fold(c))
+}
+
+// Simple one-to-one case folding. Characters whose lowercase form is more
+// than one character are left alone.
+fn fold(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+fn unfold(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(u), None) => u,
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<Range<usize>> {
+        Regex::new(pattern).unwrap().find(text)
+    }
+
+    #[test]
+    fn literals_and_classes() {
+        assert_eq!(Some(1..4), find("ust", "Rust:"));
+        assert_eq!(Some(4..7), find(r"\d+", "abc 123 def"));
+        assert_eq!(Some(0..3), find("[a-c]+", "abcd"));
+        assert_eq!(Some(3..4), find("[^a-c]", "abcd"));
+        assert_eq!(None, find("[xyz]", "abcd"));
+    }
+
+    #[test]
+    fn anchors() {
+        let re = Regex::new(r"^ERROR.*\d$").unwrap();
+        assert!(re.is_match("ERROR disk full at 42"));
+        assert!(!re.is_match("WARN ERROR 42"));
+        assert!(!re.is_match("ERROR disk full"));
+
+        assert_eq!(Some(4..7), find(r"\bcat\b", "the cat sat"));
+        assert_eq!(None, find(r"\bcat\b", "concatenate"));
+    }
+
+    #[test]
+    fn multi_line_anchors_and_crlf() {
+        let re = RegexBuilder::new(r"^\w+$").multi_line(true).build().unwrap();
+        let text = "one two\r\nfoo\r\nbar\n";
+        assert_eq!(Some(9..12), re.find(text));
+        assert_eq!(Some(14..17), re.find_at(text, 12));
+        // The '\r' is part of the line ending, not the line.
+        assert!(!RegexBuilder::new(r"foo\r$").multi_line(true).build().unwrap().is_match(text));
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        assert_eq!(Some(4..8), find("disk|network", "bad disk"));
+        assert_eq!(Some(0..6), find("(ab)+", "ababab"));
+        assert_eq!(Some(0..7), find("(?:foo|bar)baz!?", "barbaz!!"));
+
+        let re = Regex::new(r"(\w+)=(\d+)?(x)?").unwrap();
+        assert_eq!(
+            Some(vec![Some(2..7), Some(2..4), Some(5..7), None]),
+            re.captures_at("; id=42", 0)
+        );
+    }
+
+    #[test]
+    fn quantifiers() {
+        assert_eq!(Some(0..4), find("a{2,4}", "aaaaa"));
+        assert_eq!(Some(0..2), find("a{2,4}?", "aaaaa"));
+        assert_eq!(Some(0..6), find("<.*>", "<a><b>"));
+        assert_eq!(Some(0..3), find("<.*?>", "<a><b>"));
+        assert_eq!(Some(0..3), find("x{,", "x{,"));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let re = RegexBuilder::new("rust").case_insensitive(true).build().unwrap();
+        assert_eq!(Some(1..5), re.find("TRUST me"));
+        assert!(Regex::new("(?i)ÉTÉ").unwrap().is_match("un été"));
+
+        // Full case folding applies to the pattern only.
+        let re = RegexBuilder::new("straße").case_insensitive(true).build().unwrap();
+        assert_eq!(Some(4..11), re.find("DIE STRASSE"));
+        assert_eq!(Some(4..11), re.find("die Straße"));
+        assert!(!Regex::new("(?i)strasse").unwrap().is_match("die Straße"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("unclosed group", Regex::new("(ab").unwrap_err().message);
+        assert_eq!("unopened group", Regex::new("ab)").unwrap_err().message);
+        assert_eq!(
+            "repetition operator missing expression",
+            Regex::new("*a").unwrap_err().message
+        );
+        assert_eq!("invalid class range", Regex::new("[z-a]").unwrap_err().message);
+    }
+
+    #[test]
+    fn nested_repeats_are_capped() {
+        let too_large = "pattern too large once repetitions are expanded";
+        assert_eq!(too_large, Regex::new("((a{1000}){1000}){1000}").unwrap_err().message);
+        assert_eq!(too_large, Regex::new("(a{1,1000}){1000}").unwrap_err().message);
+        assert!(Regex::new(r"(\w{1,100}){100}").is_ok());
+    }
+}
@@ -0,0 +1,235 @@
+// Search and replace.
+//
+// A replacement template is plain text with references to the match: $0 or
+// ${0} is the whole match, $1, $2, ... are regex capture groups, and $$ is
+// a literal dollar sign. Without --regex only $0 has anything in it.
+//
+// --in-place rewrites every changed file by writing the new contents to a
+// temporary file next to it and renaming that over the original, so a
+// crash or a full disk never leaves a half-written file behind.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::matcher::Matcher;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replacement {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Group(usize),
+}
+
+impl Replacement {
+    pub fn new(template: &str) -> Replacement {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+
+        while let Some(dollar) = rest.find('$') {
+            text.push_str(&rest[..dollar]);
+            let after = &rest[dollar + 1..];
+
+            let (group, len) = if let Some(braced) = after.strip_prefix('{') {
+                match braced.find('}') {
+                    Some(close) => (braced[..close].parse().ok(), close + 2),
+                    None => (None, 0),
+                }
+            } else {
+                let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                (after[..digits].parse().ok(), digits)
+            };
+
+            match group {
+                Some(group) => {
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Group(group));
+                    rest = &after[len..];
+                }
+                // "$$" is a dollar sign, and so is a '$' that isn't a reference.
+                None => {
+                    text.push('$');
+                    rest = after.strip_prefix('$').unwrap_or(after);
+                }
+            }
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+
+        Replacement { pieces }
+    }
+
+    // Expands the template for one match, given the ranges of its groups.
+    pub fn expand(&self, text: &str, groups: &[Option<Range<usize>>]) -> String {
+        let mut expanded = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => expanded.push_str(text),
+                Piece::Group(group) => {
+                    if let Some(Some(range)) = groups.get(*group) {
+                        expanded.push_str(&text[range.clone()]);
+                    }
+                }
+            }
+        }
+        expanded
+    }
+}
+
+// A line that a replacement changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub number: usize,
+    pub old: String,
+    pub new: String,
+}
+
+// Replaces every match in `contents`, keeping line endings as they were.
+// Returns the new contents and the lines that changed.
+pub fn rewrite(matcher: &Matcher, contents: &str) -> (String, Vec<Change>) {
+    let mut rewritten = String::with_capacity(contents.len());
+    let mut changes = Vec::new();
+
+    for line in crate::lines(contents) {
+        let raw = &contents[line.offset..];
+        let ending = &raw[line.text.len()..raw.find('\n').map_or(raw.len(), |i| i + 1)];
+
+        let replaced = matcher
+            .match_line(line.text)
+            .and_then(|spans| matcher.replace(line.text, &spans))
+            .map(|(text, _)| text)
+            .filter(|text| text != line.text);
+        match replaced {
+            Some(text) => {
+                rewritten.push_str(&text);
+                changes.push(Change { number: line.number, old: line.text.to_string(), new: text });
+            }
+            None => rewritten.push_str(line.text),
+        }
+        rewritten.push_str(ending);
+    }
+
+    (rewritten, changes)
+}
+
+// Formats the changes as a unified diff without context lines.
+pub fn diff(path: &str, changes: &[Change]) -> String {
+    let mut diff = format!("--- a/{path}\n+++ b/{path}\n");
+    // Replacements can add lines, which shifts the new side's numbering.
+    let mut shift: isize = 0;
+    let mut i = 0;
+
+    while i < changes.len() {
+        // Neighbouring lines go into the same hunk.
+        let mut j = i + 1;
+        while j < changes.len() && changes[j].number == changes[j - 1].number + 1 {
+            j += 1;
+        }
+        let hunk = &changes[i..j];
+        let new_lines: Vec<&str> = hunk.iter().flat_map(|change| change.new.split('\n')).collect();
+        let old_start = hunk[0].number;
+        let new_start = old_start as isize + shift;
+
+        diff.push_str(&format!(
+            "@@ -{old_start},{} +{new_start},{} @@\n",
+            hunk.len(),
+            new_lines.len()
+        ));
+        for change in hunk {
+            diff.push_str(&format!("-{}\n", change.old));
+        }
+        for line in &new_lines {
+            diff.push_str(&format!("+{line}\n"));
+        }
+
+        shift += new_lines.len() as isize - hunk.len() as isize;
+        i = j;
+    }
+
+    diff
+}
+
+// Replaces the file at `path` with `contents`, keeping its permissions, or
+// creates it if there's no file there yet.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp = temp_path(path);
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// A hidden file in the same directory, so the rename stays on one file system.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!(".{name}.minigrep-{}.tmp", process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::Regex;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn templates() {
+        let text = "key=value";
+        let groups = [Some(0..9), Some(0..3), Some(4..9), None];
+
+        let expand = |template: &str| Replacement::new(template).expand(text, &groups);
+        assert_eq!("value=key", expand("$2=$1"));
+        assert_eq!("[key]s", expand("[${1}]s"));
+        assert_eq!("$1 costs $5", expand("$$1 costs $$5"));
+        assert_eq!("<key=value>", expand("<$0>$3"));
+        assert_eq!("$x ${y", expand("$x ${y"));
+    }
+
+    #[test]
+    fn rewrites_and_diffs() {
+        let regex = Regex::new(r"(\w+)\.unwrap\(\)").unwrap();
+        let matcher = Matcher::regex(regex).replacement(Replacement::new("$1?"));
+        let contents = "let a = x.unwrap();\r\nok\nlet b = y.unwrap() + z.unwrap();\nlet c = w.unwrap();";
+
+        let (rewritten, changes) = rewrite(&matcher, contents);
+        assert_eq!("let a = x?;\r\nok\nlet b = y? + z?;\nlet c = w?;", rewritten);
+        assert_eq!(
+            "--- a/main.rs\n+++ b/main.rs\n\
+             @@ -1,1 +1,1 @@\n-let a = x.unwrap();\n+let a = x?;\n\
+             @@ -3,2 +3,2 @@\n-let b = y.unwrap() + z.unwrap();\n-let c = w.unwrap();\n\
+             +let b = y? + z?;\n+let c = w?;\n",
+            diff("main.rs", &changes)
+        );
+    }
+
+    #[test]
+    fn atomic_write_replaces_file() {
+        let dir = TempDir::new("replace");
+        let path = dir.write("file.txt", "old");
+
+        write_atomically(&path, b"new").unwrap();
+
+        assert_eq!("new", fs::read_to_string(&path).unwrap());
+        assert!(!temp_path(&path).exists());
+    }
+}
@@ -0,0 +1,505 @@
+// The search engine behind the minigrep command, for programs that want
+// to search without running it.
+//
+// A Searcher pairs a Matcher with how inputs are read: their encoding,
+// whether matches may span lines, and what ends a line. It can hand back
+// the matches of an input as an iterator:
+//
+//     let searcher = SearcherBuilder::new("TODO").ignore_case(true).build()?;
+//     for found in searcher.matches(BufReader::new(File::open("notes.txt")?))? {
+//         let found = found?;
+//         println!("{}: {}", found.line_number, found.line);
+//     }
+//
+// or feed every line of it to a Sink, which decides what to do with them.
+// Vec<Match> collects the matches, Count counts them, Stream calls a
+// function for each, and the command line's Printer prints them.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::Range;
+use std::path::Path;
+use std::vec;
+
+use crate::config::ConfigError;
+use crate::encoding::{Decoder, Encoding};
+use crate::error::MinigrepError;
+use crate::matcher::{Boundary, Matcher};
+use crate::{trim_line_ending, walk, Config, Line};
+
+// How inputs are read, as opposed to what is looked for in them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReadOptions {
+    // For text without a byte order mark. None means UTF-8.
+    pub encoding: Option<Encoding>,
+    // Read whole files so that matches can span lines (-U).
+    pub multiline: bool,
+    // Lines end with NUL instead of a newline (-z).
+    pub null_data: bool,
+}
+
+impl ReadOptions {
+    pub fn terminator(&self) -> u8 {
+        if self.null_data {
+            b'\0'
+        } else {
+            b'\n'
+        }
+    }
+}
+
+// A selected line, owned so it can outlive the search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub line_number: usize,
+    // Differs from line_number only for multiline matches.
+    pub end_line_number: usize,
+    pub byte_offset: usize,
+    // The line without its line ending, after any replacement.
+    pub line: String,
+    pub spans: Vec<Range<usize>>,
+}
+
+impl From<&Line<'_>> for Match {
+    fn from(line: &Line) -> Match {
+        Match {
+            line_number: line.number,
+            end_line_number: line.end_number,
+            byte_offset: line.offset,
+            line: line.text.to_string(),
+            spans: line.spans.clone(),
+        }
+    }
+}
+
+// Receives the lines of an input as a Searcher reads them. Both methods
+// return whether to keep going; false stops the search of the input.
+pub trait Sink {
+    fn matched(&mut self, line: &Line) -> io::Result<bool>;
+
+    // Lines that weren't selected, for sinks that show context.
+    fn context(&mut self, _line: &Line) -> io::Result<bool> {
+        Ok(true)
+    }
+}
+
+impl Sink for Vec<Match> {
+    fn matched(&mut self, line: &Line) -> io::Result<bool> {
+        self.push(Match::from(line));
+        Ok(true)
+    }
+}
+
+// Counts the selected lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Count(pub usize);
+
+impl Sink for Count {
+    fn matched(&mut self, _line: &Line) -> io::Result<bool> {
+        self.0 += 1;
+        Ok(true)
+    }
+}
+
+// Calls a function for every selected line, until it returns false.
+pub struct Stream<F>(pub F);
+
+impl<F: FnMut(&Line) -> bool> Sink for Stream<F> {
+    fn matched(&mut self, line: &Line) -> io::Result<bool> {
+        Ok((self.0)(line))
+    }
+}
+
+pub struct Searcher {
+    matcher: Matcher,
+    read: ReadOptions,
+}
+
+impl Searcher {
+    pub fn new(config: &Config) -> Result<Searcher, MinigrepError> {
+        Ok(Searcher { matcher: Matcher::new(config)?, read: config.read })
+    }
+
+    pub fn with_matcher(matcher: Matcher, read: ReadOptions) -> Searcher {
+        Searcher { matcher, read }
+    }
+
+    pub fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+
+    pub fn read_options(&self) -> ReadOptions {
+        self.read
+    }
+
+    // Feeds every line of `reader` to `sink`.
+    pub fn search_reader<R: BufRead, S: Sink>(&self, reader: R, sink: &mut S) -> Result<(), MinigrepError> {
+        let (reader, _) = self.decode(reader)?;
+        self.search_decoded(reader, sink)?;
+        Ok(())
+    }
+
+    pub fn search_path<S: Sink>(&self, path: &Path, sink: &mut S) -> Result<(), MinigrepError> {
+        self.search_reader(BufReader::new(File::open(path)?), sink)
+    }
+
+    // The selected lines of `reader`, one at a time. With multiline
+    // matching the whole input is searched up front.
+    pub fn matches<'s, R: BufRead + 's>(&'s self, reader: R) -> Result<Matches<'s>, MinigrepError> {
+        let (reader, _) = self.decode(reader)?;
+        let state = if self.read.multiline {
+            let mut found = Vec::new();
+            search_multiline(&self.matcher, reader, self.read.terminator(), &mut found)?;
+            State::Collected(found.into_iter())
+        } else {
+            State::Lines(Lines::new(reader, self.read.terminator()))
+        };
+        Ok(Matches { matcher: &self.matcher, state })
+    }
+
+    // Prepares `reader` for searching: skips a byte order mark and decodes
+    // whatever encoding isn't UTF-8. Also returns whether the input looks
+    // binary, judging by its first few kilobytes.
+    pub fn decode<'r, R: BufRead + 'r>(&self, mut reader: R) -> io::Result<(Box<dyn BufRead + 'r>, bool)> {
+        let start = reader.fill_buf()?;
+        let bom = Encoding::sniff(start);
+        let encoding = bom.map(|(encoding, _)| encoding).or(self.read.encoding);
+        // NUL-separated records are full of the bytes that usually mean binary.
+        let binary = !self.read.null_data
+            && !encoding.is_some_and(Encoding::is_utf16)
+            && walk::is_binary(start);
+        reader.consume(bom.map_or(0, |(_, len)| len));
+
+        let reader: Box<dyn BufRead + 'r> = match encoding {
+            Some(encoding) if encoding != Encoding::Utf8 => {
+                Box::new(BufReader::new(Decoder::new(reader, encoding)))
+            }
+            _ => Box::new(reader),
+        };
+        Ok((reader, binary))
+    }
+
+    // Searches input that `decode` has already prepared.
+    pub fn search_decoded<R: BufRead, S: Sink>(&self, reader: R, sink: &mut S) -> io::Result<()> {
+        let terminator = self.read.terminator();
+        if self.read.multiline {
+            search_multiline(&self.matcher, reader, terminator, sink)
+        } else {
+            search_reader(&self.matcher, reader, terminator, sink)
+        }
+    }
+}
+
+// Builds a Searcher one option at a time.
+pub struct SearcherBuilder {
+    config: Config,
+}
+
+impl SearcherBuilder {
+    pub fn new(pattern: &str) -> SearcherBuilder {
+        SearcherBuilder::patterns(&[pattern])
+    }
+
+    // A line matches if it matches any of `patterns`.
+    pub fn patterns<S: AsRef<str>>(patterns: &[S]) -> SearcherBuilder {
+        let mut config = Config::default();
+        config.patterns = patterns.iter().map(|pattern| pattern.as_ref().to_string()).collect();
+        SearcherBuilder { config }
+    }
+
+    pub fn ignore_case(mut self, yes: bool) -> SearcherBuilder {
+        self.config.ignore_case = yes;
+        self
+    }
+
+    pub fn regex(mut self, yes: bool) -> SearcherBuilder {
+        self.config.regex = yes;
+        self
+    }
+
+    pub fn boundary(mut self, boundary: Boundary) -> SearcherBuilder {
+        self.config.boundary = boundary;
+        self
+    }
+
+    pub fn fuzzy(mut self, max_edits: usize) -> SearcherBuilder {
+        self.config.fuzzy = Some(max_edits);
+        self
+    }
+
+    pub fn invert(mut self, yes: bool) -> SearcherBuilder {
+        self.config.invert = yes;
+        self
+    }
+
+    pub fn replace(mut self, template: &str) -> SearcherBuilder {
+        self.config.replace = Some(template.to_string());
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> SearcherBuilder {
+        self.config.read.encoding = Some(encoding);
+        self
+    }
+
+    pub fn multiline(mut self, yes: bool) -> SearcherBuilder {
+        self.config.read.multiline = yes;
+        self
+    }
+
+    pub fn null_data(mut self, yes: bool) -> SearcherBuilder {
+        self.config.read.null_data = yes;
+        self
+    }
+
+    pub fn build(self) -> Result<Searcher, MinigrepError> {
+        if self.config.regex && self.config.fuzzy.is_some() {
+            let conflict = ConfigError::ConflictingOptions("regex".to_string(), "fuzzy".to_string());
+            return Err(conflict.into());
+        }
+        Searcher::new(&self.config)
+    }
+}
+
+// Iterator over the selected lines of an input; see Searcher::matches.
+pub struct Matches<'s> {
+    matcher: &'s Matcher,
+    state: State<'s>,
+}
+
+enum State<'s> {
+    Lines(Lines<Box<dyn BufRead + 's>>),
+    Collected(vec::IntoIter<Match>),
+    Done,
+}
+
+impl Iterator for Matches<'_> {
+    type Item = Result<Match, MinigrepError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.state {
+            State::Lines(lines) => loop {
+                match lines.next(self.matcher, |line, matched| matched.then(|| Match::from(line))) {
+                    Ok(Some(Some(found))) => return Some(Ok(found)),
+                    Ok(Some(None)) => {}
+                    Ok(None) => {
+                        self.state = State::Done;
+                        return None;
+                    }
+                    Err(err) => {
+                        self.state = State::Done;
+                        return Some(Err(err.into()));
+                    }
+                }
+            },
+            State::Collected(found) => found.next().map(Ok),
+            State::Done => None,
+        }
+    }
+}
+
+// Reads and matches one line at a time.
+struct Lines<R> {
+    reader: R,
+    terminator: u8,
+    buf: Vec<u8>,
+    number: usize,
+    offset: usize,
+}
+
+impl<R: BufRead> Lines<R> {
+    fn new(reader: R, terminator: u8) -> Lines<R> {
+        Lines { reader, terminator, buf: Vec::new(), number: 0, offset: 0 }
+    }
+
+    // Reads the next line and passes it to `f` together with whether it
+    // was selected. None at the end of the input.
+    fn next<T>(&mut self, matcher: &Matcher, f: impl FnOnce(&Line, bool) -> T) -> io::Result<Option<T>> {
+        self.buf.clear();
+        let read = self.reader.read_until(self.terminator, &mut self.buf)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.number += 1;
+        let (number, offset) = (self.number, self.offset);
+        self.offset += read;
+
+        let raw = String::from_utf8_lossy(&self.buf);
+        let text = trim_line_ending(&raw, self.terminator);
+        let spans = matcher.match_line(text);
+        let matched = spans.is_some();
+        let replaced = spans.as_ref().and_then(|spans| matcher.replace(text, spans));
+        let line = match &replaced {
+            Some((text, spans)) => Line::new(number, offset, text, spans.clone()),
+            None => Line::new(number, offset, text, spans.unwrap_or_default()),
+        };
+        Ok(Some(f(&line, matched)))
+    }
+}
+
+// Searches `reader` one line at a time, handing every line to the sink.
+// Only the current line is kept in memory, so this works on pipes and on
+// files far larger than RAM. Invalid UTF-8 is replaced with U+FFFD rather
+// than aborting the search. Lines end with `terminator`.
+pub fn search_reader<R: BufRead, S: Sink>(
+    matcher: &Matcher,
+    reader: R,
+    terminator: u8,
+    sink: &mut S,
+) -> io::Result<()> {
+    let mut lines = Lines::new(reader, terminator);
+    let mut keep_going = true;
+
+    while keep_going {
+        let next = lines.next(matcher, |line, matched| {
+            if matched {
+                sink.matched(line)
+            } else {
+                sink.context(line)
+            }
+        })?;
+        match next {
+            Some(result) => keep_going = result?,
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+// Searches all of `reader` at once, so that a match can span lines. Lines
+// a match touches are handed to the sink together as one Line; all other
+// lines are handed over one by one, as context.
+pub fn search_multiline<R: Read, S: Sink>(
+    matcher: &Matcher,
+    mut reader: R,
+    terminator: u8,
+    sink: &mut S,
+) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let contents = String::from_utf8_lossy(&bytes);
+    let matches = matcher.find_all(&contents);
+    let raw: Vec<&str> = contents.split_inclusive(char::from(terminator)).collect();
+
+    let mut i = 0;
+    let mut offset = 0;
+    let mut next_match = 0;
+    let mut keep_going = true;
+    while i < raw.len() && keep_going {
+        // Take the matches that start in this line, and the lines they run
+        // into, until a line is reached that no match starts in.
+        let first_match = next_match;
+        let mut last = i;
+        let mut end = offset + raw[i].len();
+        while let Some(span) = matches.get(next_match).filter(|span| span.start < end) {
+            let reach = if span.is_empty() { span.start } else { span.end - 1 };
+            while reach >= end {
+                last += 1;
+                end += raw[last].len();
+            }
+            next_match += 1;
+        }
+        let touched = first_match < next_match;
+
+        if matcher.is_inverted() || !touched {
+            // Each line on its own, selected if no match touches it.
+            for (k, raw) in raw[i..=last].iter().enumerate() {
+                let text = trim_line_ending(raw, terminator);
+                let line = Line::new(i + k + 1, offset, text, Vec::new());
+                keep_going = if matcher.is_inverted() && !touched {
+                    sink.matched(&line)?
+                } else {
+                    sink.context(&line)?
+                };
+                offset += raw.len();
+                if !keep_going {
+                    break;
+                }
+            }
+        } else {
+            let text = trim_line_ending(&contents[offset..end], terminator);
+            let spans: Vec<Range<usize>> = matches[first_match..next_match]
+                .iter()
+                .map(|span| span.start - offset..(span.end - offset).min(text.len()))
+                .collect();
+            let replaced = matcher.replace(text, &spans);
+            let mut line = match &replaced {
+                Some((text, spans)) => Line::new(i + 1, offset, text, spans.clone()),
+                None => Line::new(i + 1, offset, text, spans),
+            };
+            line.end_number = last + 1;
+            keep_going = sink.matched(&line)?;
+            offset = end;
+        }
+        i = last + 1;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterates_and_collects() {
+        let contents = "fn main() {\n    todo!()\n}\n// TODO: tests\n";
+        let searcher = SearcherBuilder::new("todo").ignore_case(true).build().unwrap();
+
+        let found: Vec<Match> =
+            searcher.matches(contents.as_bytes()).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(2, found.len());
+        assert_eq!(
+            Match {
+                line_number: 4,
+                end_line_number: 4,
+                byte_offset: 26,
+                line: "// TODO: tests".to_string(),
+                spans: vec![Range { start: 3, end: 7 }],
+            },
+            found[1]
+        );
+
+        let mut collected = Vec::new();
+        searcher.search_reader(contents.as_bytes(), &mut collected).unwrap();
+        assert_eq!(found, collected);
+
+        let mut count = Count::default();
+        searcher.search_reader(contents.as_bytes(), &mut count).unwrap();
+        assert_eq!(Count(2), count);
+    }
+
+    #[test]
+    fn streams_until_stopped() {
+        let searcher = SearcherBuilder::new("a").build().unwrap();
+        let mut seen = Vec::new();
+        let mut sink = Stream(|line: &Line| {
+            seen.push(line.number);
+            seen.len() < 2
+        });
+
+        searcher.search_reader(&b"a\nb\na\na\n"[..], &mut sink).unwrap();
+        assert_eq!(vec![1, 3], seen);
+    }
+
+    #[test]
+    fn multiline_matches_report_their_lines() {
+        let searcher = SearcherBuilder::new(r"\(\s*\)").regex(true).multiline(true).build().unwrap();
+        let found: Vec<Match> =
+            searcher.matches(&b"call(\n)\nnone\n"[..]).unwrap().map(Result::unwrap).collect();
+
+        assert_eq!(1, found.len());
+        assert_eq!((1, 2), (found[0].line_number, found[0].end_line_number));
+        assert_eq!("call(\n)", found[0].line);
+    }
+
+    #[test]
+    fn builder_errors_are_typed() {
+        let err = SearcherBuilder::new("a(").regex(true).build().err().unwrap();
+        assert!(matches!(err, MinigrepError::Regex(_)));
+
+        let err = SearcherBuilder::new("a").regex(true).fuzzy(1).build().err().unwrap();
+        assert!(matches!(err, MinigrepError::Config(ConfigError::ConflictingOptions(..))));
+    }
+}
@@ -0,0 +1,171 @@
+// Where a run spends its time, for --stats.
+//
+// Reading and printing are timed as they happen: files are read through a
+// Metered reader and results written through a Timed writer. Matching is
+// whatever else searching a file took, so it includes decoding and
+// decompression. With several threads the times are summed over all of
+// them and can add up to more than the elapsed time.
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::json::Object;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub files: usize,
+    pub files_with_matches: usize,
+    pub bytes: u64,
+    pub lines: usize,
+    pub matches: usize,
+    // Finding the files to search.
+    pub walking: Duration,
+    pub reading: Duration,
+    // Searching files, reading included.
+    pub searching: Duration,
+    pub printing: Duration,
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    pub fn matching(&self) -> Duration {
+        self.searching.saturating_sub(self.reading)
+    }
+
+    pub fn write_report(&self, out: &mut impl Write) -> io::Result<()> {
+        let rows = [
+            ("Files searched", format!("{} ({} with matches)", self.files, self.files_with_matches)),
+            ("Bytes read", self.bytes.to_string()),
+            ("Lines examined", self.lines.to_string()),
+            ("Matching lines", self.matches.to_string()),
+            ("Time walking", seconds(self.walking)),
+            ("Time reading", seconds(self.reading)),
+            ("Time matching", seconds(self.matching())),
+            ("Time printing", seconds(self.printing)),
+            ("Elapsed", seconds(self.elapsed)),
+        ];
+        writeln!(out)?;
+        for (name, value) in rows {
+            writeln!(out, "{:<16}{value}", format!("{name}:"))?;
+        }
+        Ok(())
+    }
+
+    // The numbers the --json summary doesn't already have.
+    pub fn to_json(&self) -> Object {
+        Object::new()
+            .number("bytes_read", self.bytes)
+            .number("lines_examined", self.lines)
+            .number("walking_secs", seconds(self.walking))
+            .number("reading_secs", seconds(self.reading))
+            .number("matching_secs", seconds(self.matching()))
+            .number("printing_secs", seconds(self.printing))
+    }
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.6}", duration.as_secs_f64())
+}
+
+// Bytes read and time spent reading, shared by every thread of a run.
+#[derive(Debug, Default)]
+pub struct Meter {
+    bytes: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl Meter {
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn time(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+
+    fn record(&self, bytes: usize, spent: Duration) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.nanos.fetch_add(spent.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+// Reads from `inner`, recording what was read in a Meter.
+pub struct Metered<'m, R> {
+    inner: R,
+    meter: &'m Meter,
+}
+
+impl<'m, R: Read> Metered<'m, R> {
+    pub fn new(inner: R, meter: &'m Meter) -> Metered<'m, R> {
+        Metered { inner, meter }
+    }
+}
+
+impl<R: Read> Read for Metered<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let started = Instant::now();
+        let read = self.inner.read(buf)?;
+        self.meter.record(read, started.elapsed());
+        Ok(read)
+    }
+}
+
+// Writes to `inner`, adding up the time it takes.
+pub struct Timed<W> {
+    inner: W,
+    pub spent: Duration,
+}
+
+impl<W: Write> Timed<W> {
+    pub fn new(inner: W) -> Timed<W> {
+        Timed { inner, spent: Duration::ZERO }
+    }
+}
+
+impl<W: Write> Write for Timed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let started = Instant::now();
+        let written = self.inner.write(buf);
+        self.spent += started.elapsed();
+        written
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let started = Instant::now();
+        let flushed = self.inner.flush();
+        self.spent += started.elapsed();
+        flushed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meters_and_reports() {
+        let meter = Meter::default();
+        let mut text = String::new();
+        Metered::new(&b"one\ntwo\n"[..], &meter).read_to_string(&mut text).unwrap();
+        assert_eq!(8, meter.bytes());
+
+        let stats = Stats {
+            files: 2,
+            files_with_matches: 1,
+            bytes: meter.bytes(),
+            lines: 2,
+            matches: 1,
+            reading: Duration::from_millis(1),
+            searching: Duration::from_millis(3),
+            ..Stats::default()
+        };
+        assert_eq!(Duration::from_millis(2), stats.matching());
+
+        let mut report = Vec::new();
+        stats.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("Files searched: 2 (1 with matches)\n"));
+        assert!(report.contains("Time matching:  0.002000\n"));
+    }
+}
@@ -0,0 +1,46 @@
+// Temporary directories for tests. Each is removed when its TempDir is
+// dropped, so a failing test doesn't leave it behind.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    // An empty directory named after `name` and this process, so that
+    // tests running at the same time each get their own.
+    pub fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("minigrep-{name}-{}", process::id()));
+        // Left over from an earlier run that was killed.
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, relative: &str) -> PathBuf {
+        self.path.join(relative)
+    }
+
+    // Writes a file, creating the directories it goes in, and returns its
+    // path.
+    pub fn write(&self, relative: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,469 @@
+// A full-screen browser for search results (--interactive).
+//
+//   > QUERY                            the query, edited as you type
+//   path:line: text  │  preview        results on the left, the selected
+//   path:line: text  │  of the file    one's file around it on the right
+//   N matches  ...                     a status line
+//
+// Every edit of the query searches the files again. Up and Down (or Ctrl-P
+// and Ctrl-N) move through the results, Page Up and Page Down a screen at
+// a time, Enter opens the selected one in $VISUAL or $EDITOR at its line,
+// and Esc or Ctrl-C quits.
+//
+// The terminal is put into raw mode with stty(1) and drawn on with ANSI
+// escapes, so this needs a Unix terminal but no libraries. Widths are
+// counted in chars, so wide characters can push a line past its pane.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::MinigrepError;
+use crate::{Config, Match, Searcher};
+
+const REVERSE: &str = "\x1b[7m";
+const DIM: &str = "\x1b[2m";
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+// Searching stops after this many results, to stay responsive.
+const MAX_RESULTS: usize = 5000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    Enter,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    // Ctrl-U
+    ClearQuery,
+    Quit,
+}
+
+// Splits what one read from the terminal returned into keys. Escape
+// sequences other than the arrows and paging keys are dropped.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    if bytes == b"\x1b" {
+        return vec![Key::Quit];
+    }
+
+    let mut keys = Vec::new();
+    let text = String::from_utf8_lossy(bytes);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' => {
+                // CSI: ESC [ parameters final-byte
+                let mut sequence = String::new();
+                if chars.next_if_eq(&'[').is_some() || chars.next_if_eq(&'O').is_some() {
+                    for c in chars.by_ref() {
+                        sequence.push(c);
+                        if c.is_ascii_alphabetic() || c == '~' {
+                            break;
+                        }
+                    }
+                }
+                match sequence.as_str() {
+                    "A" => Key::Up,
+                    "B" => Key::Down,
+                    "5~" => Key::PageUp,
+                    "6~" => Key::PageDown,
+                    _ => continue,
+                }
+            }
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x03' => Key::Quit,
+            '\x10' => Key::Up,
+            '\x0e' => Key::Down,
+            '\x15' => Key::ClearQuery,
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+pub struct Hit {
+    pub path: PathBuf,
+    pub found: Match,
+}
+
+// What is on screen, apart from the terminal itself.
+#[derive(Default)]
+pub struct App {
+    pub query: String,
+    pub hits: Vec<Hit>,
+    // Why the query can't be searched for, if it can't.
+    pub error: Option<String>,
+    selected: usize,
+    // The first result shown in the list.
+    scroll: usize,
+    // The selected result's file, as lines.
+    preview: Option<(PathBuf, Vec<String>)>,
+}
+
+impl App {
+    pub fn selected(&self) -> Option<&Hit> {
+        self.hits.get(self.selected)
+    }
+
+    pub fn set_hits(&mut self, hits: Vec<Hit>) {
+        self.hits = hits;
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    // Moves the selection by `by` results, staying within the list.
+    pub fn select(&mut self, by: isize) {
+        let last = self.hits.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(by).min(last);
+    }
+
+    // The frame for a terminal of `rows` by `cols`, one string per row.
+    pub fn render(&mut self, rows: usize, cols: usize) -> Vec<String> {
+        let rows = rows.max(3);
+        let body = rows - 2;
+        let left = cols * 2 / 5;
+        let right = cols.saturating_sub(left + 1);
+
+        // Keep the selection in view.
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + body {
+            self.scroll = self.selected + 1 - body;
+        }
+
+        let mut frame = Vec::with_capacity(rows);
+        let mut top = format!("> {}", self.query);
+        if let Some(error) = &self.error {
+            top.push_str(&format!("   {MATCH_COLOR}{error}{RESET}"));
+        }
+        frame.push(top);
+
+        let preview = self.preview_lines(body, right);
+        for row in 0..body {
+            let index = self.scroll + row;
+            let entry = match self.hits.get(index) {
+                Some(hit) => {
+                    let prefix = format!("{}:{}: ", hit.path.display(), hit.found.line_number);
+                    let text = format!("{prefix}{}", hit.found.line.trim_start());
+                    let entry = fit(&text, &[], left, "");
+                    if index == self.selected {
+                        format!("{REVERSE}{entry}{RESET}")
+                    } else {
+                        entry
+                    }
+                }
+                None => " ".repeat(left),
+            };
+            let preview = preview.get(row).map(String::as_str).unwrap_or_default();
+            frame.push(format!("{entry}{DIM}│{RESET}{preview}"));
+        }
+
+        let count = match self.hits.len() {
+            MAX_RESULTS => format!("{MAX_RESULTS}+ matches"),
+            1 => "1 match".to_string(),
+            count => format!("{count} matches"),
+        };
+        let help = "  ↑/↓ select  Enter open  Ctrl-U clear  Esc quit";
+        frame.push(format!("{REVERSE}{}{RESET}", fit(&format!("{count}{help}"), &[], cols, "")));
+        frame
+    }
+
+    // The lines around the selected result, with its matches highlighted.
+    fn preview_lines(&mut self, rows: usize, width: usize) -> Vec<String> {
+        let Some(hit) = self.hits.get(self.selected) else {
+            return Vec::new();
+        };
+        if self.preview.as_ref().is_none_or(|(path, _)| *path != hit.path) {
+            let contents = fs::read(&hit.path).unwrap_or_default();
+            let lines = String::from_utf8_lossy(&contents).lines().map(str::to_string).collect();
+            self.preview = Some((hit.path.clone(), lines));
+        }
+        let lines = &self.preview.as_ref().expect("just set").1;
+
+        let selected = hit.found.line_number.saturating_sub(1);
+        let first = selected.saturating_sub(rows / 2);
+        let text_width = width.saturating_sub(7);
+        let window = lines.iter().enumerate().skip(first).take(rows);
+        window
+            .map(|(i, line)| {
+                let number = format!("{DIM}{:>5}{RESET}  ", i + 1);
+                if i == selected {
+                    format!("{number}{}", fit(line, &hit.found.spans, text_width, MATCH_COLOR))
+                } else {
+                    format!("{number}{}", fit(line, &[], text_width, ""))
+                }
+            })
+            .collect()
+    }
+}
+
+// Cuts or pads `text` to exactly `width` chars, painting the byte ranges
+// in `spans` with `color`. Control characters are shown as spaces.
+fn fit(text: &str, spans: &[Range<usize>], width: usize, color: &str) -> String {
+    let mut out = String::new();
+    let mut used = 0;
+    let mut painting = false;
+    for (i, c) in text.char_indices() {
+        if used == width {
+            break;
+        }
+        let inside = spans.iter().any(|span| span.contains(&i));
+        if inside != painting {
+            out.push_str(if inside { color } else { RESET });
+            painting = inside;
+        }
+        out.push(if c.is_control() { ' ' } else { c });
+        used += 1;
+    }
+    if painting {
+        out.push_str(RESET);
+    }
+    out.extend(std::iter::repeat_n(' ', width - used));
+    out
+}
+
+// Searches `paths` for `query` with the other options of `config`.
+fn search(config: &mut Config, paths: &[PathBuf], query: &str) -> Result<Vec<Hit>, MinigrepError> {
+    let mut hits = Vec::new();
+    if query.is_empty() {
+        return Ok(hits);
+    }
+    // With --query, the box holds the boolean expression instead.
+    if config.query.is_some() {
+        config.query = Some(query.to_string());
+    } else {
+        config.patterns = vec![query.to_string()];
+    }
+    let searcher = Searcher::new(config)?;
+
+    for path in paths {
+        let mut found = Vec::new();
+        // Files that can't be read just don't show up.
+        if searcher.search_path(path, &mut found).is_err() {
+            continue;
+        }
+        hits.extend(found.into_iter().map(|found| Hit { path: path.clone(), found }));
+        if hits.len() >= MAX_RESULTS {
+            hits.truncate(MAX_RESULTS);
+            break;
+        }
+    }
+    Ok(hits)
+}
+
+// Browses the results of searching `paths` until the user quits. Returns
+// whether the last query found anything.
+pub fn run(mut config: Config, paths: &[PathBuf]) -> Result<bool, MinigrepError> {
+    let mut terminal = Terminal::open()?;
+    // The query box holds one pattern; start from the first.
+    let query = config.query.clone().or_else(|| config.patterns.first().cloned());
+    let query = query.unwrap_or_default();
+    let mut app = App { query, ..App::default() };
+    app.set_hits(search(&mut config, paths, &app.query)?);
+
+    loop {
+        terminal.draw(&mut app)?;
+        for key in terminal.read_keys()? {
+            let edited = match key {
+                Key::Char(c) => {
+                    app.query.push(c);
+                    true
+                }
+                Key::Backspace => app.query.pop().is_some(),
+                Key::ClearQuery => {
+                    app.query.clear();
+                    true
+                }
+                Key::Up => {
+                    app.select(-1);
+                    false
+                }
+                Key::Down => {
+                    app.select(1);
+                    false
+                }
+                Key::PageUp | Key::PageDown => {
+                    let page = terminal.size().0.saturating_sub(2) as isize;
+                    app.select(if key == Key::PageUp { -page } else { page });
+                    false
+                }
+                Key::Enter => {
+                    if let Some(hit) = app.selected() {
+                        terminal.suspend()?;
+                        let status = editor_command(&hit.path, hit.found.line_number).status();
+                        terminal.resume()?;
+                        if let Err(err) = status {
+                            app.error = Some(format!("can't start editor: {err}"));
+                        }
+                    }
+                    false
+                }
+                Key::Quit => return Ok(!app.hits.is_empty()),
+            };
+
+            if edited {
+                // A half-typed regex or query keeps the last results up.
+                match search(&mut config, paths, &app.query) {
+                    Ok(hits) => {
+                        app.error = None;
+                        app.set_hits(hits);
+                    }
+                    Err(MinigrepError::Regex(err)) => app.error = Some(err.to_string()),
+                    Err(MinigrepError::Query(err)) => {
+                        // The full error takes three lines; the top line has room for one.
+                        app.error = Some(format!("{} at column {}", err.message, err.column));
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+    }
+}
+
+// $VISUAL or $EDITOR, which may include arguments, opening `path` at
+// `line` the way vi, emacs and nano all understand.
+fn editor_command(path: &Path, line: usize) -> Command {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or("vi"));
+    command.args(words).arg(format!("+{line}")).arg(path);
+    command
+}
+
+// The controlling terminal, in raw mode and on the alternate screen for as
+// long as this lives.
+struct Terminal {
+    tty: File,
+    // Settings to restore, as printed by `stty -g`.
+    saved: String,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Terminal> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty").map_err(|err| {
+            io::Error::new(err.kind(), format!("--interactive needs a terminal: {err}"))
+        })?;
+        let saved = stty(&tty, &["-g"])?;
+        let mut terminal = Terminal { tty, saved };
+        terminal.resume()?;
+        Ok(terminal)
+    }
+
+    fn resume(&mut self) -> io::Result<()> {
+        stty(&self.tty, &["raw", "-echo"])?;
+        write!(self.tty, "\x1b[?1049h")
+    }
+
+    // Gives the terminal back, e.g. to an editor.
+    fn suspend(&mut self) -> io::Result<()> {
+        write!(self.tty, "\x1b[?1049l\x1b[?25h")?;
+        stty(&self.tty, &[&self.saved]).map(|_| ())
+    }
+
+    // Rows and columns, or a classic 24 by 80 if stty can't tell.
+    fn size(&self) -> (usize, usize) {
+        let size = stty(&self.tty, &["size"]).ok();
+        let parsed = size.as_deref().and_then(|size| {
+            let (rows, cols) = size.split_once(' ')?;
+            Some((rows.parse().ok()?, cols.parse().ok()?))
+        });
+        parsed.unwrap_or((24, 80))
+    }
+
+    fn draw(&mut self, app: &mut App) -> io::Result<()> {
+        let (rows, cols) = self.size();
+        let mut screen = String::from("\x1b[?25l");
+        for (row, line) in app.render(rows, cols).iter().enumerate() {
+            screen.push_str(&format!("\x1b[{};1H{line}\x1b[K", row + 1));
+        }
+        // Leave the cursor at the end of the query.
+        let column = 3 + app.query.chars().count();
+        screen.push_str(&format!("\x1b[1;{column}H\x1b[?25h"));
+        self.tty.write_all(screen.as_bytes())?;
+        self.tty.flush()
+    }
+
+    // Blocks until at least one key is pressed.
+    fn read_keys(&mut self) -> io::Result<Vec<Key>> {
+        let mut buf = [0; 64];
+        loop {
+            let read = self.tty.read(&mut buf)?;
+            if read == 0 {
+                // The terminal went away.
+                return Ok(vec![Key::Quit]);
+            }
+            let keys = parse_keys(&buf[..read]);
+            if !keys.is_empty() {
+                return Ok(keys);
+            }
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // Nothing more can be done if restoring the terminal fails.
+        let _ = self.suspend();
+    }
+}
+
+// Runs stty on the terminal and returns what it printed.
+fn stty(tty: &File, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(tty.try_clone()?).output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("stty: {message}")));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn keys_are_parsed() {
+        assert_eq!(vec![Key::Quit], parse_keys(b"\x1b"));
+        assert_eq!(
+            vec![Key::Char('a'), Key::Char('é'), Key::Backspace, Key::Up, Key::PageDown, Key::Enter],
+            parse_keys("aé\x7f\x1b[A\x1b[6~\r".as_bytes())
+        );
+        // Unknown sequences, such as F1, are dropped whole.
+        assert_eq!(vec![Key::Down, Key::ClearQuery], parse_keys(b"\x1bOP\x0e\x15"));
+    }
+
+    #[test]
+    fn renders_list_and_preview() {
+        let dir = TempDir::new("tui");
+        let path = dir.write("notes.txt", "one\ntwo TODO\nthree\nfour TODO\n");
+
+        let mut config = Config::default();
+        let hits = search(&mut config, std::slice::from_ref(&path), "TODO").unwrap();
+        let mut app = App { query: "TODO".to_string(), ..App::default() };
+        app.set_hits(hits);
+        app.select(5);
+        assert_eq!(4, app.selected().unwrap().found.line_number);
+
+        let frame = app.render(5, 150);
+        assert_eq!(5, frame.len());
+        assert_eq!("> TODO", frame[0]);
+        let name = path.display().to_string();
+        assert!(frame[1].starts_with(&format!("{name}:2: two TODO ")));
+        assert!(frame[2].starts_with(&format!("{REVERSE}{name}:4: four TODO ")));
+        assert!(frame[2].ends_with(&format!("four {MATCH_COLOR}TODO{RESET}{}", " ".repeat(73))));
+        assert!(frame[4].contains("2 matches"));
+    }
+}
@@ -0,0 +1,324 @@
+// Unicode case folding and normalization for case-insensitive searches.
+//
+// A line is turned into a sequence of units: the characters of its folded
+// (and optionally normalized) form, each remembering the byte range of the
+// original character it came from. Searching the units instead of a folded
+// copy of the line means every match maps straight back onto the original
+// text, even when folding changes the length ("ß" becomes "ss") or
+// normalization splits or merges characters ("é" versus "e" + U+0301).
+
+mod tables;
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use tables::{CASE_FOLDING, COMBINING_CLASSES, COMPOSITION_EXCLUSIONS, DECOMPOSITIONS};
+
+// Hangul syllables are composed and decomposed arithmetically.
+const HANGUL_BASE: u32 = 0xAC00;
+const HANGUL_COUNT: u32 = 11172;
+const LEAD_BASE: u32 = 0x1100;
+const VOWEL_BASE: u32 = 0x1161;
+const TRAIL_BASE: u32 = 0x11A7;
+const VOWEL_COUNT: u32 = 21;
+const TRAIL_COUNT: u32 = 28;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Normalization {
+    #[default]
+    None,
+    // Canonical composition.
+    Nfc,
+    // Canonical decomposition.
+    Nfd,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Folding {
+    // Apply full case folding.
+    pub case: bool,
+    // Fold 'I' to dotless 'ı' and 'İ' to 'i', as in Turkish and Azerbaijani.
+    pub turkic: bool,
+    pub normalization: Normalization,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    pub c: char,
+    // Byte range in the original text.
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Folding {
+    pub fn units(&self, text: &str) -> Vec<Unit> {
+        let mut units = Vec::with_capacity(text.len());
+        let mut expanded = Vec::new();
+
+        for (start, c) in text.char_indices() {
+            let end = start + c.len_utf8();
+            expanded.clear();
+            self.expand(c, &mut expanded);
+            units.extend(expanded.iter().map(|&c| Unit { c, start, end }));
+        }
+
+        match self.normalization {
+            Normalization::None => units,
+            Normalization::Nfd => reorder(units),
+            Normalization::Nfc => compose(reorder(units)),
+        }
+    }
+
+    pub fn chars(&self, text: &str) -> Vec<char> {
+        self.units(text).iter().map(|unit| unit.c).collect()
+    }
+
+    fn expand(&self, c: char, out: &mut Vec<char>) {
+        let normalize = self.normalization != Normalization::None;
+        let mut decomposed = Vec::new();
+        if normalize {
+            decompose(c, &mut decomposed);
+        } else {
+            decomposed.push(c);
+        }
+
+        for d in decomposed {
+            if !self.case {
+                out.push(d);
+                continue;
+            }
+            // Folding can produce precomposed characters, so decompose again.
+            for f in fold(d, self.turkic) {
+                if normalize {
+                    decompose(f, out);
+                } else {
+                    out.push(f);
+                }
+            }
+        }
+    }
+}
+
+// Whether the units in `range` cover whole characters of the original text.
+// A match must also not be followed by a combining mark, so "s" does not
+// match half of "ß" and "e" does not match the start of "é".
+pub fn is_whole(units: &[Unit], range: Range<usize>) -> bool {
+    let (i, j) = (range.start, range.end);
+    let starts_char = i == 0 || i == units.len() || units[i - 1].start != units[i].start;
+    let ends_char = j == 0
+        || j == units.len()
+        || (units[j - 1].start != units[j].start && combining_class(units[j].c) == 0);
+    starts_char && ends_char
+}
+
+// Byte range of the original text covered by the units in `range`.
+pub fn span(units: &[Unit], range: Range<usize>) -> Range<usize> {
+    if range.is_empty() {
+        let at = match units.get(range.start) {
+            Some(unit) => unit.start,
+            None => units.last().map_or(0, |unit| unit.end),
+        };
+        return at..at;
+    }
+
+    let matched = &units[range];
+    let start = matched.iter().map(|unit| unit.start).min().unwrap();
+    let end = matched.iter().map(|unit| unit.end).max().unwrap();
+    start..end
+}
+
+// Index of the first unit that starts at or after byte offset `from`,
+// looking no earlier than index `after`. Searching forward along a line,
+// passing the index found for the last offset keeps the scan linear.
+pub fn position(units: &[Unit], from: usize, after: usize) -> usize {
+    let skipped = units[after..].iter().position(|unit| unit.start >= from);
+    skipped.map_or(units.len(), |i| after + i)
+}
+
+fn fold(c: char, turkic: bool) -> Vec<char> {
+    match c {
+        'I' if turkic => return vec!['ı'],
+        'İ' if turkic => return vec!['i'],
+        _ => {}
+    }
+    match CASE_FOLDING.binary_search_by_key(&c, |&(from, _)| from) {
+        Ok(i) => CASE_FOLDING[i].1.chars().collect(),
+        Err(_) => c.to_lowercase().collect(),
+    }
+}
+
+fn decompose(c: char, out: &mut Vec<char>) {
+    let code = c as u32;
+    if (HANGUL_BASE..HANGUL_BASE + HANGUL_COUNT).contains(&code) {
+        let s = code - HANGUL_BASE;
+        let per_lead = VOWEL_COUNT * TRAIL_COUNT;
+        out.push(char::from_u32(LEAD_BASE + s / per_lead).unwrap());
+        out.push(char::from_u32(VOWEL_BASE + (s % per_lead) / TRAIL_COUNT).unwrap());
+        if !s.is_multiple_of(TRAIL_COUNT) {
+            out.push(char::from_u32(TRAIL_BASE + s % TRAIL_COUNT).unwrap());
+        }
+        return;
+    }
+
+    match DECOMPOSITIONS.binary_search_by_key(&c, |&(from, _, _)| from) {
+        Ok(i) => {
+            let (_, first, second) = DECOMPOSITIONS[i];
+            decompose(first, out);
+            if second != '\0' {
+                decompose(second, out);
+            }
+        }
+        Err(_) => out.push(c),
+    }
+}
+
+fn combining_class(c: char) -> u8 {
+    if c < '\u{300}' {
+        return 0;
+    }
+    let found = COMBINING_CLASSES.binary_search_by(|&(first, last, _)| {
+        if last < c {
+            std::cmp::Ordering::Less
+        } else if first > c {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    });
+    found.map_or(0, |i| COMBINING_CLASSES[i].2)
+}
+
+// Puts runs of combining marks into canonical order. The sort is stable,
+// so marks with the same class keep their relative order.
+fn reorder(mut units: Vec<Unit>) -> Vec<Unit> {
+    let mut i = 0;
+    while i < units.len() {
+        if combining_class(units[i].c) == 0 {
+            i += 1;
+            continue;
+        }
+        let run_start = i;
+        while i < units.len() && combining_class(units[i].c) != 0 {
+            i += 1;
+        }
+        units[run_start..i].sort_by_key(|unit| combining_class(unit.c));
+    }
+    units
+}
+
+// Canonical composition of already decomposed and reordered units.
+fn compose(units: Vec<Unit>) -> Vec<Unit> {
+    let mut composed: Vec<Unit> = Vec::with_capacity(units.len());
+    let mut starter = None;
+    // Class of the last unit kept since the starter, if any.
+    let mut last_class = None;
+
+    for unit in units {
+        let class = combining_class(unit.c);
+        if let Some(s) = starter {
+            let blocked = matches!(last_class, Some(last) if last == 0 || last >= class);
+            if !blocked {
+                let base: &mut Unit = &mut composed[s];
+                if let Some(c) = compose_pair(base.c, unit.c) {
+                    base.c = c;
+                    base.start = base.start.min(unit.start);
+                    base.end = base.end.max(unit.end);
+                    continue;
+                }
+            }
+        }
+
+        if class == 0 {
+            starter = Some(composed.len());
+            last_class = None;
+        } else {
+            last_class = Some(class);
+        }
+        composed.push(unit);
+    }
+
+    composed
+}
+
+fn compose_pair(first: char, second: char) -> Option<char> {
+    let (a, b) = (first as u32, second as u32);
+
+    // Leading consonant + vowel, then syllable + trailing consonant.
+    if (LEAD_BASE..LEAD_BASE + 19).contains(&a) && (VOWEL_BASE..VOWEL_BASE + VOWEL_COUNT).contains(&b) {
+        let syllable = (a - LEAD_BASE) * VOWEL_COUNT + (b - VOWEL_BASE);
+        return char::from_u32(HANGUL_BASE + syllable * TRAIL_COUNT);
+    }
+    let is_lv = (HANGUL_BASE..HANGUL_BASE + HANGUL_COUNT).contains(&a)
+        && (a - HANGUL_BASE).is_multiple_of(TRAIL_COUNT);
+    if is_lv && (TRAIL_BASE + 1..TRAIL_BASE + TRAIL_COUNT).contains(&b) {
+        return char::from_u32(a + b - TRAIL_BASE);
+    }
+
+    compositions().get(&(first, second)).copied()
+}
+
+fn compositions() -> &'static HashMap<(char, char), char> {
+    static COMPOSITIONS: OnceLock<HashMap<(char, char), char>> = OnceLock::new();
+
+    COMPOSITIONS.get_or_init(|| {
+        DECOMPOSITIONS
+            .iter()
+            .filter(|&&(c, _, second)| second != '\0' && !COMPOSITION_EXCLUSIONS.contains(&c))
+            .map(|&(c, first, second)| ((first, second), c))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aho_corasick::AhoCorasick;
+
+    fn find_str(folding: Folding, query: &str, text: &str) -> Option<Range<usize>> {
+        let units = folding.units(text);
+        let chars: Vec<char> = units.iter().map(|unit| unit.c).collect();
+        let ac = AhoCorasick::new(&[folding.chars(query)]);
+        let found = ac.find_at(&chars, 0, |range| is_whole(&units, range))?;
+        Some(span(&units, found))
+    }
+
+    #[test]
+    fn full_case_folding() {
+        let folding = Folding { case: true, ..Default::default() };
+
+        assert_eq!(Some(4..11), find_str(folding, "STRASSE", "Die Straße"));
+        assert_eq!(Some(0..7), find_str(folding, "straße", "STRASSE"));
+        // Half of a folded 'ß' is not a match.
+        assert_eq!(None, find_str(folding, "s", "ß"));
+        // Final sigma folds like any other sigma.
+        assert_eq!(Some(0..6), find_str(folding, "ΣΑΣ", "σας"));
+    }
+
+    #[test]
+    fn turkic_dotless_i() {
+        let folding = Folding { case: true, ..Default::default() };
+        let turkic = Folding { case: true, turkic: true, ..Default::default() };
+
+        assert_eq!(None, find_str(folding, "ılık", "ILIK"));
+        assert_eq!(Some(0..4), find_str(turkic, "ılık", "ILIK"));
+        assert_eq!(Some(0..9), find_str(turkic, "istanbul", "İstanbul"));
+    }
+
+    #[test]
+    fn normalization_matches_either_encoding() {
+        let composed = "caf\u{e9} cr\u{e8}me";
+        let decomposed = "cafe\u{301} cre\u{300}me";
+
+        for normalization in [Normalization::Nfc, Normalization::Nfd] {
+            let folding = Folding { normalization, ..Default::default() };
+            assert_eq!(Some(0..12), find_str(folding, decomposed, composed));
+            assert_eq!(Some(7..14), find_str(folding, "crème", decomposed));
+            // A bare 'e' doesn't match the start of an accented one.
+            assert_eq!(None, find_str(folding, "cafe", decomposed));
+        }
+
+        let folding = Folding { normalization: Normalization::Nfc, ..Default::default() };
+        assert_eq!(vec!['\u{d55c}'], folding.chars("\u{1112}\u{1161}\u{11ab}"));
+    }
+}
@@ -0,0 +1,242 @@
+// Recursive directory traversal for searching whole project trees.
+//
+// By default hidden files and directories are skipped, and so is anything
+// that .gitignore, .ignore or git's global excludes ignore. The .git
+// directory itself is never searched.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::glob::Glob;
+use crate::ignore::{self, IgnoreFile};
+
+// How many leading bytes to inspect when deciding if a file is binary.
+pub const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+pub struct Filter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    // Also walk into hidden files and directories.
+    hidden: bool,
+    // Honor ignore files.
+    ignore_files: bool,
+}
+
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> Filter {
+        Filter {
+            include: include.iter().map(|pattern| Glob::new(pattern)).collect(),
+            exclude: exclude.iter().map(|pattern| Glob::new(pattern)).collect(),
+            hidden: false,
+            ignore_files: true,
+        }
+    }
+
+    pub fn hidden(mut self, yes: bool) -> Filter {
+        self.hidden = yes;
+        self
+    }
+
+    pub fn ignore_files(mut self, yes: bool) -> Filter {
+        self.ignore_files = yes;
+        self
+    }
+
+    // Excludes apply to directories too, so "--exclude target" prunes the
+    // whole directory. Includes only apply to files.
+    fn allows(&self, relative: &str, is_dir: bool) -> bool {
+        if self.exclude.iter().any(|glob| glob.is_match(relative)) {
+            return false;
+        }
+        is_dir || self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(relative))
+    }
+}
+
+// A directory that couldn't be read while walking.
+pub type WalkError = (PathBuf, io::Error);
+
+// Returns every file under `root` that passes the filter, sorted so the
+// output order doesn't depend on the file system. Directories that can't be
+// read are returned separately instead of ending the walk.
+pub fn files(root: &Path, filter: &Filter) -> (Vec<PathBuf>, Vec<WalkError>) {
+    let mut walk = Walk {
+        root,
+        // Ignore rules are matched against absolute paths, since they can
+        // come from directories above the root.
+        absolute_root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+        filter,
+        ignores: Vec::new(),
+        found: Vec::new(),
+        errors: Vec::new(),
+    };
+    if filter.ignore_files {
+        walk.ignores = ignore::inherited(&walk.absolute_root);
+    }
+    walk.visit(root);
+    (walk.found, walk.errors)
+}
+
+struct Walk<'a> {
+    root: &'a Path,
+    absolute_root: PathBuf,
+    filter: &'a Filter,
+    // Ignore files that apply to the directory being visited, from lowest
+    // to highest priority.
+    ignores: Vec<IgnoreFile>,
+    found: Vec<PathBuf>,
+    errors: Vec<WalkError>,
+}
+
+impl Walk<'_> {
+    fn absolute(&self, path: &Path) -> PathBuf {
+        self.absolute_root.join(path.strip_prefix(self.root).unwrap_or(path))
+    }
+
+    fn visit(&mut self, dir: &Path) {
+        let added = if self.filter.ignore_files {
+            let files = ignore::in_dir(&self.absolute(dir));
+            let added = files.len();
+            self.ignores.extend(files);
+            added
+        } else {
+            0
+        };
+
+        self.visit_entries(dir);
+        self.ignores.truncate(self.ignores.len() - added);
+    }
+
+    fn visit_entries(&mut self, dir: &Path) {
+        let entries = fs::read_dir(dir).and_then(|entries| entries.collect::<Result<Vec<_>, _>>());
+        let mut entries = match entries {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.errors.push((dir.to_path_buf(), err));
+                return;
+            }
+        };
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            // Symlinks are not followed, which also rules out cycles.
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(err) => {
+                    self.errors.push((path, err));
+                    continue;
+                }
+            };
+            let is_dir = file_type.is_dir();
+
+            let name = entry.file_name();
+            let hidden = name.to_string_lossy().starts_with('.');
+            if (hidden && !self.filter.hidden) || (is_dir && name == ".git") {
+                continue;
+            }
+            let absolute = self.absolute(&path);
+            if self.filter.ignore_files && ignore::is_ignored(&self.ignores, &absolute, is_dir) {
+                continue;
+            }
+            if !self.filter.allows(&relative_path(self.root, &path), is_dir) {
+                continue;
+            }
+
+            if is_dir {
+                self.visit(&path);
+            } else if file_type.is_file() {
+                self.found.push(path);
+            }
+        }
+    }
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let parts: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+    parts.join("/")
+}
+
+// Guesses from the start of a file whether it is binary rather than text.
+// A NUL byte settles it, as it does for grep. Otherwise it is binary if
+// more than one byte in ten is a control character that text doesn't use.
+// UTF-16 is full of NULs, so files with a byte order mark should be
+// checked with encoding::Encoding::sniff first.
+pub fn is_binary(start: &[u8]) -> bool {
+    let sample = &start[..start.len().min(BINARY_SNIFF_LEN)];
+    let controls = sample.iter().filter(|&&b| is_binary_control(b)).count();
+    sample.contains(&0) || controls * 10 > sample.len()
+}
+
+fn is_binary_control(b: u8) -> bool {
+    // Tabs, line endings, form feeds and the escapes of colored logs are text.
+    (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r' | 0x1b)) || b == 0x7f
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn walks_and_filters() {
+        let dir = TempDir::new("walk");
+        let root = dir.path();
+        dir.write("src/lib.rs", "fn a() {}");
+        dir.write("src/nested/mod.rs", "fn b() {}");
+        dir.write("src/notes.txt", "notes");
+        dir.write("target/out.rs", "fn c() {}");
+
+        let filter = Filter::new(&["*.rs".to_string()], &["target".to_string()]);
+        let (found, errors) = files(root, &filter);
+        let found: Vec<_> = found
+            .iter()
+            .map(|path| relative_path(root, path))
+            .collect();
+
+        assert_eq!(vec!["src/lib.rs", "src/nested/mod.rs"], found);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn honors_ignore_files_and_hidden() {
+        let dir = TempDir::new("ignore");
+        let root = dir.path();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        dir.write(".gitignore", "target/\n*.log\n!keep.log\n");
+        dir.write(".ignore", "src/gen/\n");
+        dir.write("src/.gitignore", "*.tmp\n");
+        let names = ["src/lib.rs", "src/a.tmp", "src/gen/out.rs", "target/debug/x", "run.log", "keep.log", ".env"];
+        for file in names {
+            dir.write(file, "text");
+        }
+
+        let walk = |filter: &Filter| -> Vec<String> {
+            let (found, _) = files(root, filter);
+            found.iter().map(|path| relative_path(root, path)).collect()
+        };
+
+        assert_eq!(vec!["keep.log", "src/lib.rs"], walk(&Filter::new(&[], &[])));
+        assert_eq!(
+            vec![".env", ".gitignore", ".ignore", "keep.log", "src/.gitignore", "src/lib.rs"],
+            walk(&Filter::new(&[], &[]).hidden(true))
+        );
+        assert_eq!(
+            vec!["keep.log", "run.log", "src/a.tmp", "src/gen/out.rs", "src/lib.rs", "target/debug/x"],
+            walk(&Filter::new(&[], &[]).ignore_files(false))
+        );
+
+        // Searching a subdirectory still honors the ignore files above it.
+        let (found, _) = files(&root.join("src"), &Filter::new(&[], &[]));
+        assert_eq!(vec![root.join("src/lib.rs")], found);
+    }
+
+    #[test]
+    fn binary_files_are_skipped() {
+        assert!(!is_binary(b"text"));
+        assert!(!is_binary(b"col\tcol\r\n\x1b[31mred\x1b[0m\x0c"));
+        assert!(is_binary(b"\x7fELF\0\0"));
+        assert!(is_binary(b"\x01\x02\x03\x04text"));
+    }
+}
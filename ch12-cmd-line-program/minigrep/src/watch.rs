@@ -0,0 +1,357 @@
+// Following files as they grow (--follow) and noticing when files change
+// (--watch).
+//
+// A Tail reads whatever has been appended to a file since it last looked,
+// and notices when the file is truncated or replaced by a new one, as log
+// rotation does. A Snapshot remembers the size and modification time of a
+// set of files, to tell which of them changed. Between looks, a Waiter
+// blocks until inotify reports a change in a watched directory, or, where
+// inotify isn't available, sleeps for POLL_INTERVAL.
+
+mod inotify;
+
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use inotify::Inotify;
+
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Even with inotify, look again this often. A directory created after its
+// parent was watched isn't watched itself until the next look.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+// How much of a file a Tail reads at a time.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+// The most Tail::poll reads in one call. Whatever is left is read by the
+// next one.
+const MAX_POLL_READ: usize = 16 * READ_BUFFER_SIZE;
+
+// An unterminated line is passed on in pieces of this size rather than
+// kept whole until it ends.
+const MAX_PARTIAL_LINE: usize = 16 * READ_BUFFER_SIZE;
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    // Whole lines appended to the file. `first_line` is the number of the
+    // first of them and `offset` its byte offset in the file.
+    Lines { first_line: usize, offset: usize, bytes: Vec<u8> },
+    // The file shrank, so it is read again from the start. Like tail -f,
+    // this misses a file that was truncated and then written past its old
+    // size between two polls.
+    Truncated,
+    // Another file took its place, which is read from the start.
+    Replaced,
+}
+
+pub struct Tail {
+    path: PathBuf,
+    file: File,
+    id: Option<FileId>,
+    terminator: u8,
+    // Where the next read starts, just past the last whole line.
+    position: u64,
+    // The start of a line that hasn't been terminated yet.
+    pending: Vec<u8>,
+    lines: usize,
+}
+
+impl Tail {
+    // Opens `path` at its end, so that only lines appended from now on are
+    // read. An unterminated last line is read once it's finished.
+    pub fn open(path: &Path, terminator: u8) -> io::Result<Tail> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        if metadata.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "is a directory"));
+        }
+
+        // Count the existing lines, so that new ones are numbered right,
+        // a buffer at a time so that a large log isn't read into memory.
+        let mut buf = vec![0; READ_BUFFER_SIZE];
+        let (mut lines, mut position, mut read) = (0, 0, 0);
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            let chunk = &buf[..n];
+            lines += chunk.iter().filter(|&&b| b == terminator).count();
+            if let Some(last) = chunk.iter().rposition(|&b| b == terminator) {
+                position = read + last as u64 + 1;
+            }
+            read += n as u64;
+        }
+        file.seek(SeekFrom::Start(position))?;
+
+        Ok(Tail {
+            path: path.to_path_buf(),
+            file,
+            id: file_id(&metadata),
+            terminator,
+            position,
+            pending: Vec::new(),
+            lines,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Reads what changed since the last call, up to MAX_POLL_READ bytes of
+    // it. Until it returns no events, there may be more.
+    pub fn poll(&mut self) -> io::Result<Vec<Event>> {
+        let mut events = Vec::new();
+
+        // While a file is being rotated, there may briefly be none.
+        let current = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(events),
+            Err(err) => return Err(err),
+        };
+
+        if file_id(&current) != self.id {
+            // Whatever was written to the old file before it was replaced
+            // still counts, including a last line without a terminator.
+            if !self.read_appended(&mut events)? {
+                return Ok(events);
+            }
+            if !self.pending.is_empty() {
+                let bytes = std::mem::take(&mut self.pending);
+                let offset = self.position as usize;
+                events.push(Event::Lines { first_line: self.lines + 1, offset, bytes });
+            }
+
+            self.file = File::open(&self.path)?;
+            self.id = file_id(&self.file.metadata()?);
+            self.restart();
+            events.push(Event::Replaced);
+        } else if current.len() < self.position + self.pending.len() as u64 {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.restart();
+            events.push(Event::Truncated);
+        }
+
+        self.read_appended(&mut events)?;
+        Ok(events)
+    }
+
+    fn restart(&mut self) {
+        self.position = 0;
+        self.pending.clear();
+        self.lines = 0;
+    }
+
+    // Reads to the end of the file, or MAX_POLL_READ bytes further, and
+    // adds the whole lines found to `events`, keeping any unterminated line
+    // for later. Returns whether the end was reached.
+    fn read_appended(&mut self, events: &mut Vec<Event>) -> io::Result<bool> {
+        let mut buf = vec![0; READ_BUFFER_SIZE];
+        let mut read = 0;
+        while read < MAX_POLL_READ {
+            let n = match self.file.read(&mut buf) {
+                Ok(0) => return Ok(true),
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            read += n;
+            let start = self.pending.len();
+            self.pending.extend_from_slice(&buf[..n]);
+
+            let end = match self.pending[start..].iter().rposition(|&b| b == self.terminator) {
+                Some(last) => start + last + 1,
+                None if self.pending.len() >= MAX_PARTIAL_LINE => self.pending.len(),
+                None => continue,
+            };
+            let rest = self.pending.split_off(end);
+            let bytes = std::mem::replace(&mut self.pending, rest);
+            let first_line = self.lines + 1;
+            let offset = self.position as usize;
+            self.lines += bytes.iter().filter(|&&b| b == self.terminator).count();
+            self.position += bytes.len() as u64;
+            events.push(Event::Lines { first_line, offset, bytes });
+        }
+        Ok(false)
+    }
+}
+
+// Identifies a file independently of its name, to notice when another file
+// is renamed over it.
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+// Without inode numbers, only truncation can be noticed.
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<FileId> {
+    None
+}
+
+// The size and modification time of each file, as last seen.
+#[derive(Default)]
+pub struct Snapshot {
+    files: HashMap<PathBuf, (u64, Option<SystemTime>)>,
+}
+
+impl Snapshot {
+    // Records the current state of `paths`, forgetting any other files,
+    // and returns the paths that are new or changed since the last update.
+    pub fn update(&mut self, paths: &[PathBuf]) -> Vec<PathBuf> {
+        let mut files = HashMap::with_capacity(paths.len());
+        let mut changed = Vec::new();
+
+        for path in paths {
+            // A file that vanished since it was found is simply left out.
+            let Ok(metadata) = fs::metadata(path) else {
+                continue;
+            };
+            let state = (metadata.len(), metadata.modified().ok());
+            if self.files.get(path) != Some(&state) {
+                changed.push(path.clone());
+            }
+            files.insert(path.clone(), state);
+        }
+
+        self.files = files;
+        changed
+    }
+}
+
+// Sleeps until something may have changed.
+pub struct Waiter {
+    inotify: Option<Inotify>,
+}
+
+impl Waiter {
+    // Falls back to polling if inotify can't be set up.
+    pub fn new() -> Waiter {
+        Waiter { inotify: Inotify::new().ok() }
+    }
+
+    // Wakes up on changes to entries of `dir`. Directories that are
+    // already watched are skipped.
+    pub fn watch(&mut self, dir: &Path) {
+        if let Some(inotify) = &mut self.inotify {
+            // A directory that can't be watched is still rescanned.
+            let _ = inotify.add(dir);
+        }
+    }
+
+    // Returns once a watched directory has changed, or after a while in
+    // any case.
+    pub fn wait(&mut self) {
+        let Some(inotify) = &mut self.inotify else {
+            thread::sleep(POLL_INTERVAL);
+            return;
+        };
+
+        if inotify.wait(RESCAN_INTERVAL).is_err() {
+            // Keep going by polling instead.
+            self.inotify = None;
+        }
+    }
+}
+
+impl Default for Waiter {
+    fn default() -> Waiter {
+        Waiter::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+    use std::io::Write;
+
+    fn lines(events: &[Event]) -> Vec<(usize, usize, &str)> {
+        let lines = events.iter().filter_map(|event| match event {
+            Event::Lines { first_line, offset, bytes } => {
+                Some((*first_line, *offset, std::str::from_utf8(bytes).unwrap()))
+            }
+            _ => None,
+        });
+        lines.collect()
+    }
+
+    #[test]
+    fn tail_reads_appended_lines() {
+        let dir = TempDir::new("tail");
+        let path = dir.write("app.log", "one\ntwo\nthr");
+
+        let mut tail = Tail::open(&path, b'\n').unwrap();
+        assert_eq!(Vec::<Event>::new(), tail.poll().unwrap());
+
+        let mut log = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        log.write_all(b"ee\nfour\nfi").unwrap();
+        assert_eq!(vec![(3, 8, "three\nfour\n")], lines(&tail.poll().unwrap()));
+        log.write_all(b"ve\n").unwrap();
+        assert_eq!(vec![(5, 19, "five\n")], lines(&tail.poll().unwrap()));
+
+        // Truncated in place, as by `> app.log`.
+        fs::write(&path, "new\n").unwrap();
+        let events = tail.poll().unwrap();
+        assert_eq!(Event::Truncated, events[0]);
+        assert_eq!(vec![(1, 0, "new\n")], lines(&events));
+
+        // Renamed away and replaced, as log rotation does.
+        if cfg!(unix) {
+            let mut log = fs::OpenOptions::new().append(true).open(&path).unwrap();
+            log.write_all(b"last").unwrap();
+            fs::rename(&path, dir.join("app.log.1")).unwrap();
+            fs::write(&path, "fresh\n").unwrap();
+            let events = tail.poll().unwrap();
+            assert!(events.contains(&Event::Replaced));
+            assert_eq!(vec![(2, 4, "last"), (1, 0, "fresh\n")], lines(&events));
+        }
+
+        // Lines are counted across more than one read.
+        let big = dir.write("big.log", format!("{}par", "line\n".repeat(30_000)));
+        let mut tail = Tail::open(&big, b'\n').unwrap();
+        let mut log = fs::OpenOptions::new().append(true).open(&big).unwrap();
+        log.write_all(b"tial\n").unwrap();
+        assert_eq!(vec![(30_001, 150_000, "partial\n")], lines(&tail.poll().unwrap()));
+
+        // An overlong line is passed on in pieces, a poll's worth at a time.
+        log.write_all(&vec![b'x'; MAX_PARTIAL_LINE * 5 / 2]).unwrap();
+        let mut pieces = Vec::new();
+        loop {
+            let events = tail.poll().unwrap();
+            if events.is_empty() {
+                break;
+            }
+            pieces.extend(lines(&events).iter().map(|&(line, _, bytes)| (line, bytes.len())));
+        }
+        assert_eq!(vec![(30_002, MAX_PARTIAL_LINE), (30_002, MAX_PARTIAL_LINE)], pieces);
+        log.write_all(b"\n").unwrap();
+        let events = tail.poll().unwrap();
+        let (line, _, rest) = lines(&events)[0];
+        assert_eq!((30_002, MAX_PARTIAL_LINE / 2 + 1), (line, rest.len()));
+    }
+
+    #[test]
+    fn snapshot_reports_changes() {
+        let dir = TempDir::new("snapshot");
+        let (a, b) = (dir.write("a.txt", "a"), dir.write("b.txt", "b"));
+        let paths = vec![a.clone(), b.clone()];
+
+        let mut snapshot = Snapshot::default();
+        assert_eq!(paths, snapshot.update(&paths));
+        assert!(snapshot.update(&paths).is_empty());
+        fs::write(&b, "bb").unwrap();
+        assert_eq!(vec![b.clone()], snapshot.update(&paths));
+    }
+}
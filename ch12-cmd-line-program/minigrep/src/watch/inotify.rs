@@ -0,0 +1,151 @@
+// Change notification through Linux's inotify, declared by hand to stay
+// free of dependencies. On other systems Inotify::new always fails, and
+// callers poll instead.
+
+#[cfg(target_os = "linux")]
+pub use linux::Inotify;
+#[cfg(not(target_os = "linux"))]
+pub use other::Inotify;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::HashSet;
+    use std::ffi::{c_char, c_int, c_short, c_ulong, CString};
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    #[repr(C)]
+    struct PollFd {
+        fd: c_int,
+        events: c_short,
+        revents: c_short,
+    }
+
+    extern "C" {
+        fn inotify_init1(flags: c_int) -> c_int;
+        fn inotify_add_watch(fd: c_int, pathname: *const c_char, mask: u32) -> c_int;
+        fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+    }
+
+    const POLLIN: c_short = 0x1;
+
+    const IN_NONBLOCK: c_int = 0o4000;
+    const IN_CLOEXEC: c_int = 0o2000000;
+
+    const IN_MODIFY: u32 = 0x2;
+    const IN_ATTRIB: u32 = 0x4;
+    const IN_CLOSE_WRITE: u32 = 0x8;
+    const IN_MOVED_FROM: u32 = 0x40;
+    const IN_MOVED_TO: u32 = 0x80;
+    const IN_CREATE: u32 = 0x100;
+    const IN_DELETE: u32 = 0x200;
+    const IN_DELETE_SELF: u32 = 0x400;
+    const IN_MOVE_SELF: u32 = 0x800;
+    const MASK: u32 = IN_MODIFY
+        | IN_ATTRIB
+        | IN_CLOSE_WRITE
+        | IN_MOVED_FROM
+        | IN_MOVED_TO
+        | IN_CREATE
+        | IN_DELETE
+        | IN_DELETE_SELF
+        | IN_MOVE_SELF;
+
+    pub struct Inotify {
+        // The inotify descriptor, opened non-blocking.
+        file: File,
+        watched: HashSet<PathBuf>,
+    }
+
+    impl Inotify {
+        pub fn new() -> io::Result<Inotify> {
+            // SAFETY: inotify_init1 takes no pointers. A non-negative
+            // result is a new descriptor that nothing else owns.
+            let fd = unsafe { inotify_init1(IN_NONBLOCK | IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: as above, the descriptor is valid and now owned by `file`.
+            let file = unsafe { File::from_raw_fd(fd) };
+            Ok(Inotify { file, watched: HashSet::new() })
+        }
+
+        pub fn add(&mut self, dir: &Path) -> io::Result<()> {
+            if self.watched.contains(dir) {
+                return Ok(());
+            }
+            let path = CString::new(dir.as_os_str().as_bytes())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            // SAFETY: `path` is a NUL-terminated string that outlives the call.
+            let wd = unsafe { inotify_add_watch(self.file.as_raw_fd(), path.as_ptr(), MASK) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.watched.insert(dir.to_path_buf());
+            Ok(())
+        }
+
+        // Blocks until events arrive or `timeout` passes, and returns
+        // whether any did.
+        pub fn wait(&mut self, timeout: Duration) -> io::Result<bool> {
+            let deadline = Instant::now() + timeout;
+            loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                let mut fd = PollFd { fd: self.file.as_raw_fd(), events: POLLIN, revents: 0 };
+                // SAFETY: `fd` is a single valid pollfd that outlives the call.
+                let ready = unsafe { poll(&mut fd, 1, left.as_millis() as c_int) };
+                if ready < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(err);
+                }
+                return if ready == 0 { Ok(false) } else { self.changed() };
+            }
+        }
+
+        // Whether any events arrived since the last call. Doesn't block.
+        fn changed(&mut self) -> io::Result<bool> {
+            // Large enough for any single event, name included.
+            let mut buf = [0; 4096];
+            let mut changed = false;
+            loop {
+                match self.file.read(&mut buf) {
+                    Ok(0) => return Ok(changed),
+                    Ok(_) => changed = true,
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(changed),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod other {
+    use std::io;
+    use std::path::Path;
+    use std::time::Duration;
+
+    pub struct Inotify;
+
+    impl Inotify {
+        pub fn new() -> io::Result<Inotify> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "inotify is only available on Linux"))
+        }
+
+        pub fn add(&mut self, _dir: &Path) -> io::Result<()> {
+            Ok(())
+        }
+
+        pub fn wait(&mut self, _timeout: Duration) -> io::Result<bool> {
+            Ok(false)
+        }
+    }
+}